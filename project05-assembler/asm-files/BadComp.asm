@@ -0,0 +1,2 @@
+@2
+D=X+1
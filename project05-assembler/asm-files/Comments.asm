@@ -0,0 +1,4 @@
+// a full line comment
+@5 // load constant
+    	// whitespace then a comment
+D=A// no space before the comment
@@ -0,0 +1,6 @@
+// This program has no instructions.
+
+/* Not even
+   inside a block comment */
+   	
+// The end
@@ -0,0 +1,4 @@
+@SCREEN
+D=A
+@KBD
+D=M
@@ -0,0 +1,6 @@
+(END)
+@END
+0;JMP
+(END)
+@END
+0;JMP
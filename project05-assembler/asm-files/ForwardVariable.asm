@@ -0,0 +1,5 @@
+@result
+M=0
+(LOOP)
+@LOOP
+0;JMP
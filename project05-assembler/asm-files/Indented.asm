@@ -0,0 +1,3 @@
+   @5
+		D=M 	
+  	(LOOP)	
@@ -0,0 +1,18 @@
+// Labels are bound to ROM addresses, comments and blank lines are not counted
+
+(START)
+// load the counter
+@i
+
+M=1
+(LOOP)
+  // i++
+  @i
+
+  M=M+1
+  // back to the loop
+
+  @LOOP
+(END)
+@END
+0;JMP
@@ -0,0 +1 @@
+@32768
@@ -0,0 +1,3 @@
+// An input name with several dots
+@7
+D=A
@@ -0,0 +1,7 @@
+// Computes R0 = 2 + 3, with no newline after the last line
+@2
+D=A
+@3
+D=D+A
+@0
+M=D
\ No newline at end of file
@@ -0,0 +1,3 @@
+(SCREEN)
+@SCREEN
+0;JMP
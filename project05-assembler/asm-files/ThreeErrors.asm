@@ -0,0 +1,6 @@
+@40000
+D=X+1
+(LOOP)
+@LOOP
+(LOOP)
+0;JMP
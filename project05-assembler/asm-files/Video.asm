@@ -0,0 +1,3 @@
+// Fills the first word of a video buffer mapped outside the screen
+@VIDEO
+M=-1
@@ -0,0 +1,5 @@
+// sets up the stack pointer
+@256
+D=A
+@SP
+M=D
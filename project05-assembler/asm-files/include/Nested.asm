@@ -0,0 +1,4 @@
+// include "Outer.asm"
+(END)
+@END
+0;JMP
@@ -0,0 +1,3 @@
+// include "Init.asm"
+@LCL
+M=0
@@ -0,0 +1,2 @@
+// include "SelfInclude.asm"
+@0
@@ -0,0 +1,3 @@
+// include "Init.asm"
+@2
+D=A
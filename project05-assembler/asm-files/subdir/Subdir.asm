@@ -0,0 +1,5 @@
+// a program in a subdirectory of asm-files
+(START)
+@i
+0;JMP
+@j
@@ -0,0 +1,42 @@
+//! Measures the throughput of the buffered and streaming APIs over a large
+//! generated program: `cargo bench`.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use project05_assembler::hack_assembler::HackAssembler;
+
+mod program;
+
+const LOOPS: usize = 4000;
+const RUNS: u32 = 20;
+
+fn main() {
+    let source = program::generate_program(LOOPS);
+    let instructions = HackAssembler::assemble_str(&source).unwrap().len();
+    println!("{} instructions, best of {} runs", instructions, RUNS);
+
+    report("assemble_str", instructions, || {
+        HackAssembler::assemble_str(&source).unwrap();
+    });
+    report("assemble_streaming", instructions, || {
+        HackAssembler::assemble_streaming(source.as_bytes(), io::sink()).unwrap();
+    });
+}
+
+// Times `RUNS` runs of `assemble` and prints the best as instructions/second
+fn report<F: FnMut()>(name: &str, instructions: usize, mut assemble: F) {
+    let best = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            assemble();
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or(Duration::ZERO);
+    let per_second = instructions as f64 / best.as_secs_f64();
+    println!(
+        "{:<20} {:>10.2?}  {:>12.0} instructions/s",
+        name, best, per_second
+    );
+}
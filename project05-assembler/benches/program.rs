@@ -0,0 +1,13 @@
+/// A program of `loops` counting loops, each with its own label, variable
+/// and constant, so both passes and the allocator get a workout. Each loop
+/// is seven instructions; keep `loops` under 4681 to fit the 32K ROM.
+pub fn generate_program(loops: usize) -> String {
+    let mut source = String::new();
+    for index in 0..loops {
+        source.push_str(&format!(
+            "(LOOP{0})\n@counter{0}\nM=M+1\nD=M\n@LOOP{0}\nD;JGT\n@{0}\nD=A\n",
+            index
+        ));
+    }
+    source
+}
@@ -0,0 +1,280 @@
+use std::fmt;
+
+use crate::{
+    error::AssemblerError,
+    parser::{comp_mnemonic, dest_mnemonic, jump_mnemonic},
+};
+
+/// Translates `.hack` machine code back into Hack assembly.
+pub struct Disassembler;
+
+impl Disassembler {
+    /// Disassembles one 16-character binary word per line. A-instructions that
+    /// address `SCREEN` or `KBD` are written symbolically; every other
+    /// A-instruction keeps its numeric value, since 0..=15 are as likely to be
+    /// constants as registers.
+    pub fn disassemble_str(machine: &str) -> Result<String, AssemblerError> {
+        let mut source = String::new();
+        for line in machine.lines() {
+            let word = line.trim();
+            if word.is_empty() {
+                continue;
+            }
+            source.push_str(&disassemble_word(word)?);
+            source.push('\n');
+        }
+        Ok(source)
+    }
+}
+
+/// One machine word taken apart into its fields.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DecodedInstruction {
+    /// `@value`, with the 15-bit value.
+    A(u16),
+    /// `dest=comp;jump`, with `None` for a missing dest or jump.
+    C {
+        dest: Option<&'static str>,
+        comp: &'static str,
+        jump: Option<&'static str>,
+    },
+    /// A word that is no instruction: a C-instruction not prefixed with
+    /// `111`, or with a comp the standard ALU does not have.
+    Invalid(u16),
+}
+
+impl fmt::Display for DecodedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodedInstruction::A(value) => write!(f, "@{}", value),
+            DecodedInstruction::C { dest, comp, jump } => {
+                if let Some(dest) = dest {
+                    write!(f, "{}=", dest)?;
+                }
+                write!(f, "{}", comp)?;
+                if let Some(jump) = jump {
+                    write!(f, ";{}", jump)?;
+                }
+                Ok(())
+            }
+            DecodedInstruction::Invalid(word) => write!(f, "{:016b}", word),
+        }
+    }
+}
+
+/// Decodes one machine word. A-instructions keep their numeric value.
+pub fn decode_instruction(word: u16) -> DecodedInstruction {
+    // 0 vvvvvvvvvvvvvvv
+    if word & 0x8000 == 0 {
+        return DecodedInstruction::A(word);
+    }
+
+    // 111 a cccccc ddd jjj
+    let bits = format!("{:016b}", word);
+    if !bits.starts_with("111") {
+        return DecodedInstruction::Invalid(word);
+    }
+    match comp_mnemonic(&bits[3..10]) {
+        Some(comp) => DecodedInstruction::C {
+            dest: dest_mnemonic(&bits[10..13]),
+            comp,
+            jump: jump_mnemonic(&bits[13..16]),
+        },
+        None => DecodedInstruction::Invalid(word),
+    }
+}
+
+fn disassemble_word(word: &str) -> Result<String, AssemblerError> {
+    if word.len() != 16 || !word.chars().all(|c| c == '0' || c == '1') {
+        return Err(AssemblerError::InvalidWord(word.to_string()));
+    }
+    match decode_instruction(u16::from_str_radix(word, 2).unwrap()) {
+        DecodedInstruction::A(16384) => Ok("@SCREEN".to_string()),
+        DecodedInstruction::A(24576) => Ok("@KBD".to_string()),
+        DecodedInstruction::Invalid(_) => Err(AssemblerError::InvalidWord(word.to_string())),
+        instruction => Ok(instruction.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hack_assembler::HackAssembler;
+
+    #[test]
+    fn should_disassemble_a_and_c_instructions() {
+        let machine = "0000000000000010\n1110110000010000\n1110001100000001\n";
+        assert_eq!(
+            Disassembler::disassemble_str(machine).unwrap(),
+            "@2\nD=A\nD;JGT\n"
+        );
+    }
+
+    #[test]
+    fn should_name_the_screen_and_keyboard_addresses() {
+        let machine = "0100000000000000\n0110000000000000\n";
+        assert_eq!(
+            Disassembler::disassemble_str(machine).unwrap(),
+            "@SCREEN\n@KBD\n"
+        );
+    }
+
+    #[test]
+    fn should_round_trip_hand_written_instructions() {
+        let source = "@2\nD=A\n@KBD\nD=M\n@SCREEN\nM=-1\nAM=M-1\nD;JGT\n0;JMP\nADM=D|M;JLE\n";
+        let machine: String = HackAssembler::assemble_str(source)
+            .unwrap()
+            .iter()
+            .map(|word| format!("{:016b}\n", word))
+            .collect();
+        assert_eq!(Disassembler::disassemble_str(&machine).unwrap(), source);
+    }
+
+    #[test]
+    fn should_reject_malformed_words() {
+        for word in [
+            "0101",
+            "000000000000002x",
+            "1010000000000000",
+            "1111111111000000",
+        ] {
+            assert!(matches!(
+                Disassembler::disassemble_str(word),
+                Err(AssemblerError::InvalidWord(w)) if w == word
+            ));
+        }
+    }
+
+    #[test]
+    fn decode_instruction_should_decode_an_a_word() {
+        assert_eq!(
+            decode_instruction(0b0000000000010001),
+            DecodedInstruction::A(17)
+        );
+        assert_eq!(
+            decode_instruction(0b0111111111111111),
+            DecodedInstruction::A(32767)
+        );
+    }
+
+    #[test]
+    fn decode_instruction_should_decode_a_c_word_into_its_mnemonics() {
+        let decoded = decode_instruction(0b1111110111011010);
+        assert_eq!(
+            decoded,
+            DecodedInstruction::C {
+                dest: Some("DM"),
+                comp: "M+1",
+                jump: Some("JEQ"),
+            }
+        );
+        assert_eq!(decoded.to_string(), "DM=M+1;JEQ");
+        assert_eq!(
+            decode_instruction(0b1110101010000111),
+            DecodedInstruction::C {
+                dest: None,
+                comp: "0",
+                jump: Some("JMP"),
+            }
+        );
+    }
+
+    #[test]
+    fn decode_instruction_should_flag_words_that_are_no_instruction() {
+        assert_eq!(
+            decode_instruction(0b1010000000000000),
+            DecodedInstruction::Invalid(0b1010000000000000)
+        );
+        assert_eq!(
+            decode_instruction(0b1111111111000000),
+            DecodedInstruction::Invalid(0b1111111111000000)
+        );
+    }
+
+    // A xorshift generator: no dependencies, and the same seed always gives
+    // the same programs, so a failure can be replayed
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+            choices[self.next() as usize % choices.len()]
+        }
+    }
+
+    // Every mnemonic the reverse tables know for `bits`-wide codes
+    fn mnemonics(bits: usize, mnemonic: fn(&str) -> Option<&'static str>) -> Vec<&'static str> {
+        (0..1 << bits)
+            .filter_map(|code| mnemonic(&format!("{:0width$b}", code, width = bits)))
+            .collect()
+    }
+
+    fn random_program(rng: &mut Rng, length: usize) -> String {
+        let comps = mnemonics(7, comp_mnemonic);
+        let mut dests = mnemonics(3, dest_mnemonic);
+        dests.push("");
+        let mut jumps = mnemonics(3, jump_mnemonic);
+        jumps.push("");
+        let mut source = String::new();
+        for _ in 0..length {
+            if rng.next().is_multiple_of(2) {
+                source.push_str(&format!("@{}\n", rng.next() % 32768));
+                continue;
+            }
+            let dest = rng.pick(&dests);
+            if !dest.is_empty() {
+                source.push_str(dest);
+                source.push('=');
+            }
+            source.push_str(rng.pick(&comps));
+            let jump = rng.pick(&jumps);
+            if !jump.is_empty() {
+                source.push(';');
+                source.push_str(jump);
+            }
+            source.push('\n');
+        }
+        source
+    }
+
+    fn machine_code(words: &[u16]) -> String {
+        words
+            .iter()
+            .map(|word| format!("{:016b}\n", word))
+            .collect()
+    }
+
+    #[test]
+    fn random_programs_should_survive_an_assemble_disassemble_round_trip() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        for run in 0..200 {
+            let source = random_program(&mut rng, 50);
+            let words = HackAssembler::assemble_str(&source).unwrap();
+            let disassembled = Disassembler::disassemble_str(&machine_code(&words)).unwrap();
+            let reassembled = HackAssembler::assemble_str(&disassembled).unwrap();
+            assert_eq!(reassembled, words, "run {} of:\n{}", run, source);
+        }
+    }
+
+    #[test]
+    fn every_reverse_table_entry_should_assemble_to_its_own_code() {
+        for comp in mnemonics(7, comp_mnemonic) {
+            let word = HackAssembler::assemble_str(&format!("D={}", comp)).unwrap()[0];
+            assert_eq!(comp_mnemonic(&format!("{:016b}", word)[3..10]), Some(comp));
+        }
+        for dest in mnemonics(3, dest_mnemonic) {
+            let word = HackAssembler::assemble_str(&format!("{}=0", dest)).unwrap()[0];
+            assert_eq!(dest_mnemonic(&format!("{:016b}", word)[10..13]), Some(dest));
+        }
+        for jump in mnemonics(3, jump_mnemonic) {
+            let word = HackAssembler::assemble_str(&format!("0;{}", jump)).unwrap()[0];
+            assert_eq!(jump_mnemonic(&format!("{:016b}", word)[13..16]), Some(jump));
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use std::{error::Error, fmt, io, str::Utf8Error};
+
+#[derive(Debug)]
+pub enum AssemblerError {
+    Io(io::Error),
+    InvalidUtf8(Utf8Error),
+    UnknownComp(String),
+    UnknownDest(String),
+    UnknownJump(String),
+    UndefinedSymbol(String),
+    InvalidSymbol(String),
+    MalformedLine(String),
+    MissingComp(String),
+    ConstantOutOfRange(i32),
+    DuplicateLabel(String),
+    DuplicateSymbol(String),
+    ReservedSymbol(String),
+    InvalidDirective(String),
+    IncludeCycle(String),
+    InvalidWord(String),
+    ProgramTooLarge(usize),
+    RamExhausted(String),
+    AddressOutOfRange {
+        symbol: String,
+        address: usize,
+    },
+    MaxRamTooLarge(usize),
+    AtLine {
+        line: usize,
+        error: Box<AssemblerError>,
+    },
+}
+
+/// A problem worth pointing out that still lets the program assemble.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AssemblerWarning {
+    UnusedLabel { line: usize, label: String },
+    ShadowedPredefined { line: usize, label: String },
+}
+
+impl fmt::Display for AssemblerWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssemblerWarning::UnusedLabel { line, label } => {
+                write!(f, "line {}: label '{}' is never used", line, label)
+            }
+            AssemblerWarning::ShadowedPredefined { line, label } => {
+                write!(
+                    f,
+                    "line {}: label '{}' shadows a predefined symbol",
+                    line, label
+                )
+            }
+        }
+    }
+}
+
+/// An error shown with the source line it points at, like rustc does:
+///
+/// ```text
+/// line 2: unknown comp 'D+X'
+///   |
+/// 2 | D=D+X
+///   |   ^^^
+/// ```
+///
+/// Without a line, or with one past the end of the source, it is the plain
+/// error message.
+pub struct Diagnostic<'a> {
+    error: &'a AssemblerError,
+    source: &'a str,
+    color: bool,
+}
+
+impl Diagnostic<'_> {
+    /// Highlights the gutter and the carets with ANSI colors, for terminals.
+    pub fn with_color(self, color: bool) -> Self {
+        Self { color, ..self }
+    }
+}
+
+impl fmt::Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        let AssemblerError::AtLine { line, error } = self.error else {
+            return Ok(());
+        };
+        let Some(text) = line
+            .checked_sub(1)
+            .and_then(|index| self.source.lines().nth(index))
+        else {
+            return Ok(());
+        };
+        let text = text.trim_end();
+        // underline the bad token, or the whole instruction if it is not
+        // found as written; a dest sits left of the comp, a jump right of it
+        let found = match error.token() {
+            Some(token) if !token.is_empty() => match error.as_ref() {
+                AssemblerError::UnknownDest(_) => text.find(token),
+                _ => text.rfind(token),
+            }
+            .map(|start| (start, token.len())),
+            _ => None,
+        };
+        let (start, length) =
+            found.unwrap_or_else(|| (text.len() - text.trim_start().len(), text.trim().len()));
+        // keep tabs so the carets line up under the token
+        let padding: String = text[..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        let (blue, red, reset) = if self.color {
+            ("\x1b[1;34m", "\x1b[1;31m", "\x1b[0m")
+        } else {
+            ("", "", "")
+        };
+        let gutter = " ".repeat(line.to_string().len());
+        write!(f, "\n{}{} |{}", blue, gutter, reset)?;
+        write!(f, "\n{}{} |{} {}", blue, line, reset, text)?;
+        write!(
+            f,
+            "\n{}{} |{} {}{}{}{}",
+            blue,
+            gutter,
+            reset,
+            padding,
+            red,
+            "^".repeat(length.max(1)),
+            reset
+        )
+    }
+}
+
+impl AssemblerError {
+    /// Tags the error with the source line it was found on.
+    pub fn at_line(self, line: usize) -> Self {
+        AssemblerError::AtLine {
+            line,
+            error: Box::new(self),
+        }
+    }
+
+    /// Shows the error under the line of `source` it was found on; see
+    /// [`Diagnostic`].
+    pub fn diagnostic<'a>(&'a self, source: &'a str) -> Diagnostic<'a> {
+        Diagnostic {
+            error: self,
+            source,
+            color: false,
+        }
+    }
+
+    // The text in the source line that the error is about, if it names any
+    fn token(&self) -> Option<&str> {
+        match self {
+            AssemblerError::UnknownComp(token)
+            | AssemblerError::UnknownDest(token)
+            | AssemblerError::UnknownJump(token)
+            | AssemblerError::UndefinedSymbol(token)
+            | AssemblerError::InvalidSymbol(token)
+            | AssemblerError::DuplicateLabel(token)
+            | AssemblerError::DuplicateSymbol(token)
+            | AssemblerError::ReservedSymbol(token)
+            | AssemblerError::AddressOutOfRange { symbol: token, .. } => Some(token),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssemblerError::Io(err) => write!(f, "{}", err),
+            AssemblerError::InvalidUtf8(err) => write!(f, "source is not valid UTF-8: {}", err),
+            AssemblerError::UnknownComp(comp) => write!(f, "unknown comp '{}'", comp),
+            AssemblerError::UnknownDest(dest) => write!(f, "unknown dest '{}'", dest),
+            AssemblerError::UnknownJump(jump) => write!(f, "unknown jump '{}'", jump),
+            AssemblerError::UndefinedSymbol(symbol) => write!(f, "undefined symbol '{}'", symbol),
+            AssemblerError::InvalidSymbol(symbol) => write!(f, "invalid symbol '{}'", symbol),
+            AssemblerError::MalformedLine(line) => write!(f, "malformed line '{}'", line),
+            AssemblerError::MissingComp(line) => write!(f, "missing comp in '{}'", line),
+            AssemblerError::ConstantOutOfRange(value) => {
+                write!(f, "constant {} is out of range 0..=32767", value)
+            }
+            AssemblerError::DuplicateLabel(label) => write!(f, "duplicate label '{}'", label),
+            AssemblerError::DuplicateSymbol(symbol) => write!(f, "duplicate symbol '{}'", symbol),
+            AssemblerError::ReservedSymbol(symbol) => {
+                write!(f, "'{}' is a predefined symbol", symbol)
+            }
+            AssemblerError::InvalidDirective(line) => write!(f, "invalid directive '{}'", line),
+            AssemblerError::IncludeCycle(path) => write!(f, "'{}' includes itself", path),
+            AssemblerError::InvalidWord(word) => write!(f, "invalid machine word '{}'", word),
+            AssemblerError::ProgramTooLarge(count) => {
+                write!(
+                    f,
+                    "program has {} instructions, more than the ROM holds",
+                    count
+                )
+            }
+            AssemblerError::RamExhausted(symbol) => {
+                write!(f, "no RAM left for variable '{}'", symbol)
+            }
+            AssemblerError::AddressOutOfRange { symbol, address } => {
+                write!(f, "'{}' has address {}, past 32767", symbol, address)
+            }
+            AssemblerError::MaxRamTooLarge(max_ram) => write!(
+                f,
+                "max RAM {} is above 32768, past what an A-instruction can address",
+                max_ram
+            ),
+            AssemblerError::AtLine { line, error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+impl Error for AssemblerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AssemblerError::Io(err) => Some(err),
+            AssemblerError::InvalidUtf8(err) => Some(err),
+            AssemblerError::AtLine { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AssemblerError {
+    fn from(err: io::Error) -> Self {
+        AssemblerError::Io(err)
+    }
+}
+
+impl From<Utf8Error> for AssemblerError {
+    fn from(err: Utf8Error) -> Self {
+        AssemblerError::InvalidUtf8(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_errors_should_convert_into_the_io_variant() {
+        let err: AssemblerError = io::Error::new(io::ErrorKind::NotFound, "missing").into();
+        assert!(matches!(err, AssemblerError::Io(_)));
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn should_display_a_message_for_each_variant() {
+        assert_eq!(
+            AssemblerError::UnknownComp("X+1".to_string()).to_string(),
+            "unknown comp 'X+1'"
+        );
+        assert_eq!(
+            AssemblerError::UnknownDest("AX".to_string()).to_string(),
+            "unknown dest 'AX'"
+        );
+        assert_eq!(
+            AssemblerError::UnknownJump("JXX".to_string()).to_string(),
+            "unknown jump 'JXX'"
+        );
+        assert_eq!(
+            AssemblerError::UndefinedSymbol("foo".to_string()).to_string(),
+            "undefined symbol 'foo'"
+        );
+        assert_eq!(
+            AssemblerError::InvalidSymbol("1abc".to_string()).to_string(),
+            "invalid symbol '1abc'"
+        );
+        assert_eq!(
+            AssemblerError::MalformedLine("gibberish".to_string()).to_string(),
+            "malformed line 'gibberish'"
+        );
+        assert_eq!(
+            AssemblerError::MissingComp("D=".to_string()).to_string(),
+            "missing comp in 'D='"
+        );
+        assert_eq!(
+            AssemblerError::ConstantOutOfRange(40000).to_string(),
+            "constant 40000 is out of range 0..=32767"
+        );
+        assert_eq!(
+            AssemblerError::DuplicateLabel("END".to_string()).to_string(),
+            "duplicate label 'END'"
+        );
+        assert_eq!(
+            AssemblerError::DuplicateSymbol("N".to_string()).to_string(),
+            "duplicate symbol 'N'"
+        );
+        assert_eq!(
+            AssemblerError::ReservedSymbol("KBD".to_string()).to_string(),
+            "'KBD' is a predefined symbol"
+        );
+        assert_eq!(
+            AssemblerError::InvalidDirective("#define N".to_string()).to_string(),
+            "invalid directive '#define N'"
+        );
+        assert_eq!(
+            AssemblerError::IncludeCycle("a.asm".to_string()).to_string(),
+            "'a.asm' includes itself"
+        );
+        let bytes = vec![0xff];
+        let utf8_error = std::str::from_utf8(&bytes).unwrap_err();
+        assert_eq!(
+            AssemblerError::InvalidUtf8(utf8_error).to_string(),
+            "source is not valid UTF-8: invalid utf-8 sequence of 1 bytes from index 0"
+        );
+        assert_eq!(
+            AssemblerError::InvalidWord("12".to_string()).to_string(),
+            "invalid machine word '12'"
+        );
+        assert_eq!(
+            AssemblerError::ProgramTooLarge(40000).to_string(),
+            "program has 40000 instructions, more than the ROM holds"
+        );
+        assert_eq!(
+            AssemblerError::RamExhausted("x".to_string()).to_string(),
+            "no RAM left for variable 'x'"
+        );
+        assert_eq!(
+            AssemblerError::AddressOutOfRange {
+                symbol: "far".to_string(),
+                address: 32768
+            }
+            .to_string(),
+            "'far' has address 32768, past 32767"
+        );
+        assert_eq!(
+            AssemblerError::MaxRamTooLarge(99999).to_string(),
+            "max RAM 99999 is above 32768, past what an A-instruction can address"
+        );
+        assert_eq!(
+            AssemblerError::UnknownComp("D+X".to_string())
+                .at_line(14)
+                .to_string(),
+            "line 14: unknown comp 'D+X'"
+        );
+    }
+
+    #[test]
+    fn diagnostic_should_point_at_the_bad_token_in_its_line() {
+        let source = "@2\nD=A\n\tD=D+X // add\n";
+        let error = AssemblerError::UnknownComp("D+X".to_string()).at_line(3);
+        assert_eq!(
+            error.diagnostic(source).to_string(),
+            "line 3: unknown comp 'D+X'\n  |\n3 | \tD=D+X // add\n  | \t  ^^^"
+        );
+    }
+
+    #[test]
+    fn diagnostic_should_underline_the_instruction_without_a_token() {
+        let error = AssemblerError::ConstantOutOfRange(40000).at_line(1);
+        assert_eq!(
+            error.diagnostic("  @40000\n").to_string(),
+            "line 1: constant 40000 is out of range 0..=32767\n  |\n1 |   @40000\n  |   ^^^^^^"
+        );
+    }
+
+    #[test]
+    fn diagnostic_should_be_the_plain_message_without_a_source_line() {
+        let error = AssemblerError::UnknownComp("X".to_string());
+        assert_eq!(error.diagnostic("D=X\n").to_string(), "unknown comp 'X'");
+        let error = AssemblerError::UnknownComp("X".to_string()).at_line(7);
+        assert_eq!(
+            error.diagnostic("D=X\n").to_string(),
+            "line 7: unknown comp 'X'"
+        );
+    }
+
+    #[test]
+    fn diagnostic_should_color_the_gutter_and_carets_when_asked_to() {
+        let error = AssemblerError::UnknownJump("JXX".to_string()).at_line(1);
+        let colored = error.diagnostic("0;JXX").with_color(true).to_string();
+        assert!(colored.contains("\x1b[1;31m^^^\x1b[0m"));
+        assert!(colored.contains("\x1b[1;34m1 |\x1b[0m 0;JXX"));
+    }
+}
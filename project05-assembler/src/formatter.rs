@@ -0,0 +1,178 @@
+use crate::parser::blank_block_comments;
+
+// Indentation of every instruction; labels, directives and full-line comments
+// stay flush left
+const INDENT: &str = "    ";
+
+/// Rewrites Hack source in a canonical layout: labels, directives and
+/// full-line comments flush left, instructions indented, a label that shares a line with an instruction on a line of
+/// its own, runs of blank lines collapsed into one and trailing comments
+/// aligned in one column. Lines that touch a `/* ... */` comment are kept as
+/// written, and whitespace inside an instruction is left alone, since `D = A`
+/// does not assemble like `D=A`. Formatting already formatted source gives it
+/// back unchanged.
+pub fn format_source(source: &str) -> String {
+    let mut in_block = false;
+    let lines: Vec<(String, Option<&str>)> = source
+        .lines()
+        .flat_map(|line| split_line(line, &mut in_block))
+        .collect();
+
+    // trailing comments start two columns past the longest commented line
+    let comment_column = lines
+        .iter()
+        .filter(|(code, comment)| !code.is_empty() && comment.is_some())
+        .map(|(code, _)| code.len())
+        .max()
+        .map_or(0, |width| width + 2);
+
+    let mut formatted: Vec<String> = Vec::new();
+    for (code, comment) in lines {
+        let line = match comment {
+            Some(comment) if code.is_empty() => comment.to_string(),
+            Some(comment) => format!("{:width$}{}", code, comment, width = comment_column),
+            None => code,
+        };
+        // drop leading blank lines and collapse the others
+        if line.is_empty() && formatted.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        formatted.push(line);
+    }
+    while formatted.last().is_some_and(|last| last.is_empty()) {
+        formatted.pop();
+    }
+
+    formatted.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+// The lines a source line formats to, each as its indented code and its
+// comment, if any; `in_block` carries an open `/* ... */` comment over to the
+// next line
+fn split_line<'a>(line: &'a str, in_block: &mut bool) -> Vec<(String, Option<&'a str>)> {
+    if blank_block_comments(line, in_block) != line {
+        return vec![(line.trim_end().to_string(), None)];
+    }
+    let (code, comment) = match line.find("//") {
+        Some(index) => (line[..index].trim(), Some(line[index..].trim_end())),
+        None => (line.trim(), None),
+    };
+    if code.is_empty() || code.starts_with('#') {
+        return vec![(code.to_string(), comment)];
+    }
+    if !code.starts_with('(') {
+        return vec![(instruction(code), comment)];
+    }
+    match code.find(')') {
+        // the parser reads an instruction after a label as a line of its own
+        Some(end) if !code[end + 1..].trim().is_empty() => vec![
+            (code[..=end].to_string(), None),
+            (instruction(&code[end + 1..]), comment),
+        ],
+        _ => vec![(code.to_string(), comment)],
+    }
+}
+
+// An instruction indented, and otherwise as written
+fn instruction(code: &str) -> String {
+    format!("{}{}", INDENT, code.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hack_assembler::HackAssembler;
+
+    const MESSY: &str = "
+
+// Computes R0 = 2 + 3
+  @2   // load two
+D = A
+
+
+
+      @3
+    D=D+A // add three
+  (END)
+@END
+ 0;JMP
+
+
+";
+
+    const FORMATTED: &str = "// Computes R0 = 2 + 3
+    @2     // load two
+    D = A
+
+    @3
+    D=D+A  // add three
+(END)
+    @END
+    0;JMP
+";
+
+    #[test]
+    fn format_source_should_lay_out_a_messy_program_canonically() {
+        assert_eq!(format_source(MESSY), FORMATTED);
+    }
+
+    #[test]
+    fn format_source_should_leave_formatted_source_unchanged() {
+        assert_eq!(format_source(FORMATTED), FORMATTED);
+        assert_eq!(format_source(&format_source(MESSY)), format_source(MESSY));
+    }
+
+    const WITH_DIRECTIVES: &str = "#define  WIDTH 512
+/* draws a
+   line */ @WIDTH
+  (LOOP)   D=D-1 // count down
+  @LOOP
+D;JGT
+";
+
+    #[test]
+    fn format_source_should_keep_directives_block_comments_and_labels_intact() {
+        let formatted = format_source(WITH_DIRECTIVES);
+        assert_eq!(
+            formatted,
+            "#define  WIDTH 512
+/* draws a
+   line */ @WIDTH
+(LOOP)
+    D=D-1  // count down
+    @LOOP
+    D;JGT
+"
+        );
+        assert_eq!(format_source(&formatted), formatted);
+    }
+
+    #[test]
+    fn format_source_should_not_change_what_a_program_assembles_to() {
+        let sum = std::fs::read_to_string("asm-files/Sum1ToN.asm").unwrap();
+        for source in [WITH_DIRECTIVES, &sum] {
+            assert_eq!(
+                HackAssembler::assemble_str(&format_source(source)).unwrap(),
+                HackAssembler::assemble_str(source).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn format_source_should_not_make_an_invalid_program_valid() {
+        let source = "  @a b\nD = A  \n";
+        assert_eq!(format_source(source), "    @a b\n    D = A\n");
+        assert_eq!(
+            HackAssembler::assemble_str(&format_source(source))
+                .unwrap_err()
+                .to_string(),
+            HackAssembler::assemble_str(source).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn format_source_should_return_nothing_for_an_empty_program() {
+        assert_eq!(format_source(""), "");
+        assert_eq!(format_source("\n\n  \n"), "");
+    }
+}
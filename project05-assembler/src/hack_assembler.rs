@@ -1,137 +1,2306 @@
-use std::fs::OpenOptions;
-use std::io::Error;
-use std::io::Write;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 
 use crate::{
-    parser::{InstructionType, Parser},
-    symbol_table::SymbolTable,
+    error::{AssemblerError, AssemblerWarning},
+    options::{AssemblerOptions, OutputFormat},
+    parser::{comp_field, is_symbol, parse_constant, InstructionType, Parser},
+    symbol_table::{SymbolKind, SymbolTable},
 };
 
+/// Counts from the last assembly, for course analytics.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct AssemblyStats {
+    pub a_instructions: usize,
+    pub c_instructions: usize,
+    pub labels: usize,
+    /// Variables the run gave a RAM address, not counting preloaded ones.
+    pub variables: usize,
+    pub rom_words: usize,
+}
+
+impl fmt::Display for AssemblyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} A-instructions, {} C-instructions, {} labels, {} variables, {} ROM words",
+            self.a_instructions, self.c_instructions, self.labels, self.variables, self.rom_words
+        )
+    }
+}
+
+impl AssemblyStats {
+    /// The counts as one JSON object keyed by field name, for tools that
+    /// collect build metrics.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"a_instructions\": {}, \"c_instructions\": {}, \"labels\": {}, \"variables\": {}, \"rom_words\": {}}}",
+            self.a_instructions, self.c_instructions, self.labels, self.variables, self.rom_words
+        )
+    }
+}
+
 pub struct HackAssembler {
     parser: Parser,
     symbol_table: SymbolTable,
     output_file: String,
-    filename: String,
+    options: AssemblerOptions,
+    next_ram_address: usize,
+    // every label with the line it is defined on, and every symbol an
+    // A-instruction uses
+    labels: Vec<(String, usize)>,
+    referenced: HashSet<String>,
+    warnings: Vec<AssemblerWarning>,
+    stats: AssemblyStats,
 }
 
 impl HackAssembler {
-    pub fn new(filename: &str) -> Result<Self, Error> {
+    pub fn new(filename: &str) -> Result<Self, AssemblerError> {
+        Self::with_options(filename, AssemblerOptions::default())
+    }
+
+    /// Like [`HackAssembler::new`], but `execute` writes `<stem>.hack` into
+    /// `dir` instead of `hack-files/`; pass `asm-files` to write it next to
+    /// the input.
+    pub fn with_output_dir<P: AsRef<Path>>(filename: &str, dir: P) -> Result<Self, AssemblerError> {
+        let options = AssemblerOptions {
+            output_dir: dir.as_ref().to_path_buf(),
+            ..AssemblerOptions::default()
+        };
+        Self::with_options(filename, options)
+    }
+
+    /// Creates an assembler over `asm-files/{filename}` that behaves as
+    /// `options` says, writing into `options.output_dir`.
+    pub fn with_options(filename: &str, options: AssemblerOptions) -> Result<Self, AssemblerError> {
         let parser = Parser::new(filename)?;
-        let symbol_table = SymbolTable::new();
-        let name: Vec<&str> = filename.split(".").collect();
-        let file_name = name[0];
-        let output_file = format!("hack-files/{}.hack", file_name);
-        Ok(Self {
+        let output_file = output_file_for(filename, &options.output_dir);
+        Ok(Self::from_parser(parser, output_file, options))
+    }
+
+    /// Like [`HackAssembler::new`], but starting from `symbols` instead of the
+    /// predefined symbols alone, e.g. a memory map to link against;
+    /// [`SymbolTable::new`] gives a table to add them to. Labels in the file
+    /// may shadow entries added as [`SymbolKind::Predefined`], like they may
+    /// shadow `SCREEN`, and clash with any other. Variables are allocated past
+    /// the last one in `symbols`, and `reset_for` keeps `symbols` for the next
+    /// file.
+    pub fn with_symbols(filename: &str, mut symbols: SymbolTable) -> Result<Self, AssemblerError> {
+        let mut hack_assembler = Self::new(filename)?;
+        symbols.preload()?;
+        hack_assembler.next_ram_address = symbols.next_free_ram();
+        hack_assembler.symbol_table = symbols;
+        Ok(hack_assembler)
+    }
+
+    /// Creates an assembler over Hack source held in memory. It has no output
+    /// file, so use one of the `assemble_*` or `write_*` methods with it. With
+    /// no file to resolve them against, include lines stay comments; read a
+    /// file with [`crate::parser::read_source`] to splice them in first.
+    pub fn from_source(source: &str) -> Self {
+        Self::from_source_with_options(source, AssemblerOptions::default())
+    }
+
+    /// Like [`HackAssembler::from_source`], with the given options; the
+    /// output directory is ignored.
+    pub fn from_source_with_options(source: &str, options: AssemblerOptions) -> Self {
+        Self::from_parser(Parser::from_source(source), String::new(), options)
+    }
+
+    fn from_parser(mut parser: Parser, output_file: String, options: AssemblerOptions) -> Self {
+        configure(&mut parser, &options);
+        Self {
             parser,
-            symbol_table,
+            symbol_table: SymbolTable::new(),
             output_file,
-            filename: file_name.to_string(),
-        })
+            options,
+            next_ram_address: 16,
+            labels: Vec::new(),
+            referenced: HashSet::new(),
+            warnings: Vec::new(),
+            stats: AssemblyStats::default(),
+        }
+    }
+
+    /// Points the assembler at `asm-files/{filename}`, as `with_options` would,
+    /// but keeps the options of the last file, and any symbols it was created
+    /// `with_symbols`.
+    pub fn reset_for(&mut self, filename: &str) -> Result<(), AssemblerError> {
+        let mut parser = Parser::new(filename)?;
+        configure(&mut parser, &self.options);
+        self.parser = parser;
+        self.output_file = output_file_for(filename, &self.options.output_dir);
+        self.start_run();
+        Ok(())
+    }
+
+    // Forgets everything the last run found, so that each run starts from the
+    // first line with only the predefined and preloaded symbols
+    fn start_run(&mut self) {
+        self.parser.reset();
+        self.symbol_table.clear_user_symbols();
+        self.next_ram_address = self.symbol_table.next_free_ram();
+        self.labels.clear();
+        self.referenced.clear();
+        self.warnings.clear();
+        self.stats = AssemblyStats::default();
+    }
+
+    /// Assembles Hack source held in memory and returns its machine words,
+    /// without reading or writing any file.
+    pub fn assemble_str(source: &str) -> Result<Vec<u16>, AssemblerError> {
+        let mut hack_assembler = Self::from_source(source);
+        let mut words = Vec::new();
+        hack_assembler.assemble(|_, _, word| {
+            if let Some(word) = word {
+                words.push(word);
+            }
+            Ok(())
+        })?;
+        Ok(words)
+    }
+
+    /// Assembles Hack source held as bytes, such as an HTTP body, like
+    /// [`HackAssembler::assemble_str`]. Bytes that are not valid UTF-8 give
+    /// [`AssemblerError::InvalidUtf8`].
+    pub fn assemble_bytes(bytes: &[u8]) -> Result<Vec<u16>, AssemblerError> {
+        Self::assemble_str(std::str::from_utf8(bytes)?)
+    }
+
+    /// Reads the whole source from `reader`, such as stdin, then assembles it
+    /// like [`HackAssembler::assemble_str`].
+    pub fn assemble_reader<R: Read>(mut reader: R) -> Result<Vec<u16>, AssemblerError> {
+        let mut source = String::new();
+        reader.read_to_string(&mut source)?;
+        Self::assemble_str(&source)
+    }
+
+    /// Assembles the source from `reader` and writes it to `writer` in the
+    /// `.hack` text format, one word at a time as the second pass produces
+    /// it, so the output is never collected in memory. The source itself is
+    /// still read in whole, since the first pass must see every label before
+    /// the second can start.
+    pub fn assemble_streaming<R: Read, W: Write>(
+        mut reader: R,
+        writer: W,
+    ) -> Result<(), AssemblerError> {
+        let mut source = String::new();
+        reader.read_to_string(&mut source)?;
+        let mut hack_assembler = Self::from_source(&source);
+        drop(source);
+        hack_assembler.assemble_to_writer(writer)
+    }
+
+    /// Assembles the source into its `.hack` file and returns the path written.
+    /// The file is only replaced once the whole program has assembled, so a
+    /// failed run leaves any earlier output as it was.
+    pub fn execute(&mut self) -> Result<PathBuf, AssemblerError> {
+        let output_file = PathBuf::from(&self.output_file);
+        let mut words = Vec::new();
+        self.write_output(&mut words)?;
+        write_atomically(&output_file, &words)?;
+        Ok(output_file)
+    }
+
+    /// Assembles the source and writes it to `out` in the output format of
+    /// the options, padded with zero words when they ask for it.
+    pub fn write_output<W: Write>(&mut self, mut out: W) -> Result<(), AssemblerError> {
+        match self.options.format {
+            OutputFormat::Text => self.assemble_to_writer(&mut out)?,
+            OutputFormat::Binary => out.write_all(&self.assemble_binary()?)?,
+            OutputFormat::Hex => {
+                for line in self.assemble_hex()? {
+                    writeln!(out, "{}", line)?;
+                }
+            }
+        }
+        if let Some(size) = self.options.pad {
+            let padding = padding_for(self.stats.rom_words, size)?;
+            let zero: &[u8] = match self.options.format {
+                OutputFormat::Text => b"0000000000000000\n",
+                OutputFormat::Binary => &[0, 0],
+                OutputFormat::Hex => b"0000\n",
+            };
+            for _ in 0..padding {
+                out.write_all(zero)?;
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Assembles the source into a ROM image of exactly `size` words, the
+    /// program followed by zeros, or fails if the program is larger.
+    pub fn assemble_padded(&mut self, size: usize) -> Result<Vec<u16>, AssemblerError> {
+        let mut words = self.assemble_full()?.0;
+        let padding = padding_for(words.len(), size)?;
+        words.extend(std::iter::repeat_n(0, padding));
+        Ok(words)
+    }
+
+    /// Assembles the source and writes one 16-character binary line per
+    /// instruction, in the `.hack` text format, to any writer; with
+    /// `annotate` in the options, each line ends with its instruction.
+    pub fn assemble_to_writer<W: Write>(&mut self, out: W) -> Result<(), AssemblerError> {
+        let mut out = BufWriter::new(out);
+        let annotate = self.options.annotate;
+        self.assemble(|_, line, word| {
+            match word {
+                Some(word) if annotate => writeln!(out, "{:016b} // {}", word, line)?,
+                Some(word) => out.write_all(format!("{:016b}\n", word).as_bytes())?,
+                None => {}
+            }
+            Ok(())
+        })?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Assembles the source and returns each instruction as its 16-character
+    /// binary line, without the trailing newline.
+    pub fn assemble_lines(&mut self) -> Result<Vec<String>, AssemblerError> {
+        let mut lines = Vec::new();
+        self.assemble(|_, _, word| {
+            if let Some(word) = word {
+                lines.push(format!("{:016b}", word));
+            }
+            Ok(())
+        })?;
+        Ok(lines)
+    }
+
+    /// Assembles the source and returns each instruction as four uppercase hex
+    /// digits, e.g. `00FF` for `@255`.
+    pub fn assemble_hex(&mut self) -> Result<Vec<String>, AssemblerError> {
+        let mut lines = Vec::new();
+        self.assemble(|_, _, word| {
+            if let Some(word) = word {
+                lines.push(format!("{:04X}", word));
+            }
+            Ok(())
+        })?;
+        Ok(lines)
+    }
+
+    /// Assembles the source into raw bytes, two per instruction. Each 16-bit
+    /// word is big-endian, high byte first, so `@1` gives `[0x00, 0x01]`.
+    pub fn assemble_binary(&mut self) -> Result<Vec<u8>, AssemblerError> {
+        let mut bytes = Vec::new();
+        self.assemble(|_, _, word| {
+            if let Some(word) = word {
+                bytes.extend_from_slice(&word.to_be_bytes());
+            }
+            Ok(())
+        })?;
+        Ok(bytes)
+    }
+
+    /// Assembles the source into Rust source for a `u16` array constant named
+    /// `name`, e.g. `pub const ADD: [u16; 2] = [0x0002, 0xEC10];` laid out one
+    /// word per line, ready to embed in an emulator.
+    pub fn assemble_rust_array(&mut self, name: &str) -> Result<String, AssemblerError> {
+        if !is_rust_identifier(name) {
+            return Err(AssemblerError::InvalidSymbol(name.to_string()));
+        }
+        let mut words = Vec::new();
+        self.assemble(|_, _, word| {
+            if let Some(word) = word {
+                words.push(format!("    0x{:04X},\n", word));
+            }
+            Ok(())
+        })?;
+        Ok(format!(
+            "pub const {}: [u16; {}] = [\n{}];\n",
+            name,
+            words.len(),
+            words.concat()
+        ))
     }
 
-    pub fn execute(&mut self) -> Result<(), Error> {
-        // First pass: TODO create a Lines
+    /// Assembles the source and compares it with the `.hack` text in
+    /// `expected`, such as a committed output file. Returns `None` when they
+    /// match, or the 1-based line of `expected` where they first differ; a
+    /// missing or extra line counts as a difference.
+    pub fn verify_against(&mut self, expected_hack: &str) -> Result<Option<usize>, AssemblerError> {
+        let actual = self.assemble_lines()?;
+        let expected: Vec<&str> = expected_hack.lines().map(str::trim_end).collect();
+        let differs = (0..actual.len().max(expected.len()))
+            .find(|&index| actual.get(index).map(String::as_str) != expected.get(index).copied());
+        Ok(differs.map(|index| index + 1))
+    }
+
+    /// Writes a listing with each ROM address, its instruction and the emitted
+    /// word, e.g. `0000  @2  // 0000000000000010`. Labels are listed where they
+    /// are defined but take no address. An A-instruction naming a predefined
+    /// symbol also shows the address it stands for, e.g. `(KBD = 24576)`.
+    pub fn write_listing<W: Write>(&mut self, out: W) -> Result<(), AssemblerError> {
+        let mut out = BufWriter::new(out);
+        let mut address = 0;
+        let predefined = SymbolTable::new();
+        self.assemble(|_, line, word| {
+            match word {
+                Some(word) => {
+                    write!(out, "{:04}  {}  // {:016b}", address, line, word)?;
+                    // a label shadowing the name resolves elsewhere
+                    if let Some(symbol) = line
+                        .strip_prefix('@')
+                        .filter(|symbol| predefined.get_address(symbol) == Some(word as usize))
+                    {
+                        write!(out, "  ({} = {})", symbol, word)?;
+                    }
+                    writeln!(out)?;
+                    address += 1;
+                }
+                None => writeln!(out, "      {}", line)?,
+            }
+            Ok(())
+        })?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Writes a source map for debuggers as JSON: one entry per ROM address
+    /// with the source line its word came from, e.g.
+    /// `{"address": 0, "line": 7}`, in address order.
+    pub fn write_sourcemap<W: Write>(&mut self, out: W) -> Result<(), AssemblerError> {
+        let mut entries = Vec::new();
+        self.assemble(|line_number, _, word| {
+            if word.is_some() {
+                entries.push(format!(
+                    "  {{\"address\": {}, \"line\": {}}}",
+                    entries.len(),
+                    line_number
+                ));
+            }
+            Ok(())
+        })?;
+        let mut out = BufWriter::new(out);
+        write!(out, "[\n{}\n]\n", entries.join(",\n"))?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Assembles the source and returns its machine words together with a
+    /// snapshot of the resolved symbol table, for front-ends that want both.
+    pub fn assemble_full(&mut self) -> Result<(Vec<u16>, SymbolTable), AssemblerError> {
+        let mut words = Vec::new();
+        self.assemble(|_, _, word| {
+            if let Some(word) = word {
+                words.push(word);
+            }
+            Ok(())
+        })?;
+        Ok((words, self.symbol_table.clone()))
+    }
+
+    /// Assembles the source and returns each machine word with the ROM
+    /// address it loads at, for emulators that step through a program.
+    pub fn resolved_instructions(&mut self) -> Result<Vec<(u16, u16)>, AssemblerError> {
+        let mut instructions = Vec::new();
+        self.assemble(|_, _, word| {
+            if let Some(word) = word {
+                instructions.push((instructions.len() as u16, word));
+            }
+            Ok(())
+        })?;
+        Ok(instructions)
+    }
+
+    /// The symbol table, with every label and variable resolved once the
+    /// source has been assembled.
+    ///
+    /// ```
+    /// use project05_assembler::hack_assembler::HackAssembler;
+    ///
+    /// let mut hack_assembler = HackAssembler::from_source("@i\n(LOOP)\n@LOOP\n0;JMP\n");
+    /// hack_assembler.assemble_lines().unwrap();
+    /// assert_eq!(hack_assembler.symbol_table().get_address("LOOP"), Some(1));
+    /// assert_eq!(hack_assembler.symbol_table().get_address("i"), Some(16));
+    /// ```
+    pub fn symbol_table(&self) -> &SymbolTable {
+        &self.symbol_table
+    }
+
+    /// The warnings of the last assembly, such as labels that are never
+    /// used. Unlike errors they don't stop the program from assembling.
+    pub fn warnings(&self) -> &[AssemblerWarning] {
+        &self.warnings
+    }
+
+    /// The instruction, label and variable counts of the last assembly.
+    pub fn stats(&self) -> AssemblyStats {
+        self.stats
+    }
+
+    /// Writes the symbol table left by the last assembly, one `name address
+    /// kind` line per symbol, like [`SymbolTable::dump`].
+    pub fn dump_symbols<W: Write>(&self, out: W) -> Result<(), AssemblerError> {
+        self.symbol_table.dump(out)?;
+        Ok(())
+    }
+
+    /// Assembles the source like [`HackAssembler::assemble_str`], but keeps
+    /// going past bad lines and returns every error, each tagged with its line
+    /// number, instead of stopping at the first one. The errors come in
+    /// source order, whichever pass found them.
+    pub fn assemble_collecting(&mut self) -> Result<Vec<u16>, Vec<AssemblerError>> {
+        let mut words = Vec::new();
+        let mut errors = Vec::new();
+        let result = self.assemble_reporting(
+            |_, _, word| {
+                if let Some(word) = word {
+                    words.push(word);
+                }
+                Ok(())
+            },
+            |line, error| {
+                errors.push(error.at_line(line));
+                Ok(())
+            },
+        );
+        // the first pass finds label errors before the second finds the rest
+        errors.sort_by_key(|error| match error {
+            AssemblerError::AtLine { line, .. } => *line,
+            _ => 0,
+        });
+        // anything left is an io error that ended the run
+        if let Err(error) = result {
+            errors.push(error);
+        }
+        if errors.is_empty() {
+            Ok(words)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Runs both passes and every check, like [`HackAssembler::assemble_collecting`],
+    /// but only reports whether the program is valid. Nothing is written,
+    /// not even the output file `execute` would create.
+    pub fn check(&mut self) -> Result<(), Vec<AssemblerError>> {
+        self.assemble_collecting().map(|_| ())
+    }
+
+    /// Assembles the source and returns each `@symbol` that jumps to a label
+    /// never defined, with its line number. Any other unknown symbol is a
+    /// variable, but a jump target must be a ROM address: given RAM instead,
+    /// it sends the program somewhere arbitrary.
+    ///
+    /// The A register is followed from each `@symbol` to the jump that uses
+    /// it, through instructions that leave A alone, until a label or a write
+    /// to A. A variable that is also read or written through `M` is taken
+    /// for a real one, even where it is jumped to.
+    pub fn undefined_symbols(&mut self) -> Result<Vec<(String, usize)>, AssemblerError> {
+        let mut lines = Vec::new();
+        self.assemble(|line_number, line, _| {
+            lines.push((line_number, line.to_string()));
+            Ok(())
+        })?;
+        // the variable A holds, with the line that loaded it
+        let mut a_register: Option<(&str, usize)> = None;
+        let mut jumps = Vec::new();
+        let mut data = HashSet::new();
+        for (line_number, line) in &lines {
+            if let Some(symbol) = line.strip_prefix('@') {
+                a_register = (self.symbol_table.get_kind(symbol) == Some(SymbolKind::Variable))
+                    .then_some((symbol, *line_number));
+                continue;
+            }
+            let Some((dest, comp, jump)) = Parser::c_fields(line) else {
+                // control may reach a label with anything in A
+                a_register = None;
+                continue;
+            };
+            let dest = dest.unwrap_or("").to_ascii_uppercase();
+            if let Some((symbol, loaded_at)) = a_register {
+                if dest.contains('M') || comp.to_ascii_uppercase().contains('M') {
+                    data.insert(symbol);
+                }
+                if jump.is_some() {
+                    jumps.push((symbol, loaded_at));
+                }
+            }
+            if dest.contains('A') {
+                a_register = None;
+            }
+        }
+        let undefined = jumps
+            .into_iter()
+            .filter(|(symbol, _)| !data.contains(symbol))
+            .map(|(symbol, line_number)| (symbol.to_string(), line_number))
+            .collect();
+        Ok(undefined)
+    }
+
+    // Runs both passes, stopping at the first error and tagging it with its
+    // line number
+    fn assemble<F>(&mut self, emit: F) -> Result<(), AssemblerError>
+    where
+        F: FnMut(usize, &str, Option<u16>) -> Result<(), AssemblerError>,
+    {
+        self.assemble_reporting(emit, |line, error| Err(error.at_line(line)))
+    }
+
+    // Runs both passes; `emit` gets every line of the second pass with its
+    // word, or `None` for a label, and `report` gets every line-level error
+    // with its line number and decides whether the run stops there
+    fn assemble_reporting<F, R>(&mut self, mut emit: F, mut report: R) -> Result<(), AssemblerError>
+    where
+        F: FnMut(usize, &str, Option<u16>) -> Result<(), AssemblerError>,
+        R: FnMut(usize, AssemblerError) -> Result<(), AssemblerError>,
+    {
+        self.options.validate()?;
+        self.start_run();
+        self.first_pass(&mut report)?;
+        self.parser.reset();
+        self.second_pass(&mut emit, &mut report)?;
+        self.stats.rom_words = self.stats.a_instructions + self.stats.c_instructions;
+        self.stats.labels = self.labels.len();
+        let unused = self
+            .labels
+            .iter()
+            .filter(|(label, _)| !self.referenced.contains(label))
+            .map(|(label, line)| AssemblerWarning::UnusedLabel {
+                line: *line,
+                label: label.clone(),
+            });
+        self.warnings.extend(unused);
+        Ok(())
+    }
+
+    fn line_number(&self) -> usize {
+        self.parser.get_line_number()
+    }
+
+    // First pass: bind each label to the ROM address of the next instruction
+    fn first_pass<R>(&mut self, report: &mut R) -> Result<(), AssemblerError>
+    where
+        R: FnMut(usize, AssemblerError) -> Result<(), AssemblerError>,
+    {
         while let Some(Ok(line)) = self.parser.advance() {
+            if let Some(define) = self.parser.define(&line) {
+                if let Err(error) = self.define(define) {
+                    report(self.line_number(), error)?;
+                }
+                continue;
+            }
             match self.parser.instruction_type(&line) {
                 Some(InstructionType::LInstruction) => {
-                    let symbol = self.parser.symbol(line).unwrap();
-                    self.symbol_table
-                        .add_entry(symbol, self.parser.get_line_count().unwrap() + 1);
-                }
-                Some(InstructionType::AInstruction) => {
-                    let symbol = self.parser.symbol(line).unwrap();
-                    self.symbol_table
-                        .update_entry(symbol, self.parser.get_line_count().unwrap());
-                }
-                _ => continue,
-            }
-        }
-
-        // Second pass:
-        match self.parser.reinitialize_lines(format!("{}.asm", &self.filename).as_str()) {
-            Ok(()) => {
-                while let Some(Ok(line)) = self.parser.advance() {
-                  let mut file = OpenOptions::new().append(true).create(true).open(&self.output_file)?;
-                    match self.parser.instruction_type(&line) {
-                        Some(InstructionType::AInstruction) => {
-                            let symbol = self.parser.symbol(line).unwrap();
-
-                            // symbol == label -> get_address -> binary
-                            if let Some(add) = self.symbol_table.get_address(&symbol) {
-                                let binary = format!("{:016b}\n", &add);
-                                file.write_all(binary.as_bytes())?;
-                            }
-
-                            // symbol == num -> binary
-                            if let Ok(num) =  symbol.parse::<i32>() {
-                                file.write_all(format!("{:016b}", num).as_bytes())?;
-                            }
-                          }
-                        Some(InstructionType::CInstruction) => {
-                            // concatenate dest + comp + jump
-                            // 111 a cccccc ddd jjj
-                            let mut instruction = "111".to_string();
-
-                            if let Some(value) = self.parser.comp(&line) {
-                                instruction.push_str(value);
-                            }
-
-                            if let Some(value) = self.parser.dest(&line) {
-                                instruction.push_str(value);
-                            }
-
-                            if let Some(value) = self.parser.jump(&line) {
-                                instruction.push_str(value);
-                            }
-                            instruction.push_str("\n");
-                            // insert in output_file
-                            let _ = file.write_all(instruction.as_bytes());
+                    // a label missing its `)` must not bind a truncated name
+                    let Some(symbol) = self.parser.symbol(line.clone()) else {
+                        report(self.line_number(), AssemblerError::MalformedLine(line))?;
+                        continue;
+                    };
+                    // nothing could jump to it, as symbols after @ are checked too
+                    if !is_symbol(&symbol) {
+                        report(self.line_number(), AssemblerError::InvalidSymbol(line))?;
+                        continue;
+                    }
+                    // a label may take over a predefined name, but only
+                    // outside strict mode and with a warning
+                    if self.symbol_table.get_kind(&symbol) == Some(SymbolKind::Predefined) {
+                        if self.options.strict {
+                            report(self.line_number(), AssemblerError::ReservedSymbol(symbol))?;
+                            continue;
                         }
-                        _ => continue,
+                        self.warnings.push(AssemblerWarning::ShadowedPredefined {
+                            line: self.line_number(),
+                            label: symbol.clone(),
+                        });
+                        self.labels.push((symbol.clone(), self.line_number()));
+                        self.symbol_table.update_entry(
+                            symbol,
+                            self.parser.instruction_index(),
+                            SymbolKind::Label,
+                        );
+                        continue;
                     }
+                    if self.symbol_table.contains(&symbol) {
+                        report(self.line_number(), AssemblerError::DuplicateLabel(symbol))?;
+                        continue;
+                    }
+                    self.labels.push((symbol.clone(), self.line_number()));
+                    self.symbol_table.add_entry(
+                        symbol,
+                        self.parser.instruction_index(),
+                        SymbolKind::Label,
+                    );
+                }
+                // the parser counts them into instruction_index
+                Some(InstructionType::AInstruction) | Some(InstructionType::CInstruction) => {
+                    continue
                 }
+                // lenient mode skips lines that are no instruction at all
+                None if self.options.strict => {
+                    report(self.line_number(), AssemblerError::MalformedLine(line))?
+                }
+                None => continue,
             }
-            Err(err) => eprintln!("Error with reinitialization: {}", err),
         }
+        // nothing past the end of the ROM could ever run
+        let instruction_count = self.parser.instruction_index();
+        if instruction_count > self.options.rom_limit {
+            return Err(AssemblerError::ProgramTooLarge(instruction_count));
+        }
+        Ok(())
+    }
+
+    fn define(
+        &mut self,
+        define: Result<(String, u16), AssemblerError>,
+    ) -> Result<(), AssemblerError> {
+        let (name, value) = define?;
+        if !is_symbol(&name) {
+            return Err(AssemblerError::InvalidSymbol(name));
+        }
+        if self.symbol_table.contains(&name) {
+            return Err(AssemblerError::DuplicateSymbol(name));
+        }
+        self.symbol_table
+            .add_entry(name, value as usize, SymbolKind::Constant);
+        Ok(())
+    }
 
+    // Second pass: translate each instruction and hand it to `emit`
+    fn second_pass<F, R>(&mut self, emit: &mut F, report: &mut R) -> Result<(), AssemblerError>
+    where
+        F: FnMut(usize, &str, Option<u16>) -> Result<(), AssemblerError>,
+        R: FnMut(usize, AssemblerError) -> Result<(), AssemblerError>,
+    {
+        while let Some(Ok(line)) = self.parser.advance() {
+            // directives were handled by the first pass
+            if self.parser.define(&line).is_some() {
+                continue;
+            }
+            let instruction_type = self.parser.instruction_type(&line);
+            let word = match instruction_type {
+                Some(InstructionType::AInstruction) => self.translate_a(&line).map(Some),
+                Some(InstructionType::CInstruction) => self.translate_c(&line).map(Some),
+                Some(InstructionType::LInstruction) => Ok(None),
+                // already reported by the first pass in strict mode
+                None => continue,
+            };
+            match word {
+                Ok(word) => {
+                    match instruction_type {
+                        Some(InstructionType::AInstruction) => self.stats.a_instructions += 1,
+                        Some(InstructionType::CInstruction) => self.stats.c_instructions += 1,
+                        _ => {}
+                    }
+                    emit(self.line_number(), &line, word)?
+                }
+                Err(error) => report(self.line_number(), error)?,
+            }
+        }
         Ok(())
     }
+
+    fn translate_a(&mut self, line: &str) -> Result<u16, AssemblerError> {
+        let symbol = self.parser.symbol(line.to_string()).unwrap();
+
+        let address = match parse_constant(&symbol) {
+            // symbol == num -> binary
+            Some(num) => {
+                // a 15-bit A-instruction can only hold 0..=32767
+                if !(0..=32767).contains(&num) {
+                    return Err(AssemblerError::ConstantOutOfRange(num));
+                }
+                num as usize
+            }
+            None if !is_symbol(&symbol) => {
+                return Err(AssemblerError::InvalidSymbol(symbol));
+            }
+            None => {
+                self.referenced.insert(symbol.clone());
+                // symbol == variable -> allocate the next free RAM address
+                if !self.symbol_table.contains(&symbol) {
+                    // past the data segment a variable would overwrite the screen
+                    if self.next_ram_address >= self.options.max_ram {
+                        return Err(AssemblerError::RamExhausted(symbol));
+                    }
+                    self.symbol_table.add_entry(
+                        symbol.clone(),
+                        self.next_ram_address,
+                        SymbolKind::Variable,
+                    );
+                    self.next_ram_address += 1;
+                    self.stats.variables += 1;
+                }
+
+                // symbol == label or variable -> get_address
+                match self.symbol_table.get_address(&symbol) {
+                    // past 32767 the word would read as a C-instruction
+                    Some(address) if address > 0x7FFF => {
+                        return Err(AssemblerError::AddressOutOfRange { symbol, address });
+                    }
+                    Some(address) => address,
+                    None => return Err(AssemblerError::UndefinedSymbol(symbol)),
+                }
+            }
+        };
+        Ok(address as u16)
+    }
+
+    fn translate_c(&self, line: &str) -> Result<u16, AssemblerError> {
+        if !self.options.allow_lowercase {
+            check_uppercase(line)?;
+        }
+
+        // concatenate dest + comp + jump
+        // 111 a cccccc ddd jjj, or 101 for an extended ALU shift
+        let mut instruction = self.parser.c_prefix(line).to_string();
+
+        instruction.push_str(self.parser.require_comp(line)?);
+
+        if let Some(value) = self.parser.dest(line)? {
+            instruction.push_str(value);
+        }
+
+        if let Some(value) = self.parser.jump(line)? {
+            instruction.push_str(value);
+        }
+        Ok(u16::from_str_radix(&instruction, 2).unwrap())
+    }
+}
+
+/// Writes `contents` to a temporary file next to `path`, then renames it over
+/// `path`. Readers see the old file or the new one, never half of it, even
+/// if the path is also being read. Missing parent directories are created
+/// first. A named pipe or device is written to directly, since renaming over
+/// it would replace it with a plain file.
+pub fn write_atomically<P: AsRef<Path>>(path: P, contents: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    if fs::metadata(path).is_ok_and(|metadata| !metadata.is_file()) {
+        return File::create(path)?.write_all(contents);
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    let temporary = path.with_file_name(name);
+    let written = File::create(&temporary).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    match written.and_then(|()| fs::rename(&temporary, path)) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let _ = fs::remove_file(&temporary);
+            Err(err)
+        }
+    }
+}
+
+/// Compares two assembled programs word by word and returns each address
+/// where they differ, with the word of `a` and of `b`. Only the addresses both
+/// programs have are compared; a length mismatch is left to the caller.
+pub fn diff_hack(a: &[u16], b: &[u16]) -> Vec<(usize, u16, u16)> {
+    a.iter()
+        .zip(b)
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(address, (a, b))| (address, *a, *b))
+        .collect()
+}
+
+/// Reads `.hack` text, one 16-character binary word per line, back into
+/// machine words. Blank lines are skipped.
+pub fn parse_hack(machine: &str) -> Result<Vec<u16>, AssemblerError> {
+    let mut words = Vec::new();
+    for (index, line) in machine.lines().enumerate() {
+        let word = line.trim();
+        if word.is_empty() {
+            continue;
+        }
+        if word.len() != 16 || !word.chars().all(|c| c == '0' || c == '1') {
+            return Err(AssemblerError::InvalidWord(word.to_string()).at_line(index + 1));
+        }
+        words.push(u16::from_str_radix(word, 2).unwrap());
+    }
+    Ok(words)
+}
+
+// How many zero words fill a program of `count` words up to `size`
+fn padding_for(count: usize, size: usize) -> Result<usize, AssemblerError> {
+    size.checked_sub(count)
+        .ok_or(AssemblerError::ProgramTooLarge(count))
+}
+
+fn configure(parser: &mut Parser, options: &AssemblerOptions) {
+    parser.set_extended_alu(options.extended_alu);
+    parser.set_semicolon_comments(options.semicolon_comments);
+}
+
+fn is_rust_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Without allow_lowercase, dest, comp and jump must be written in uppercase
+fn check_uppercase(line: &str) -> Result<(), AssemblerError> {
+    let is_lowercase = |text: &str| text.chars().any(|c| c.is_ascii_lowercase());
+    let (rest, jump) = match line.split_once(';') {
+        Some((rest, jump)) => (rest, Some(jump)),
+        None => (line, None),
+    };
+    if let Some((dest, _)) = rest.split_once('=') {
+        if is_lowercase(dest) {
+            return Err(AssemblerError::UnknownDest(dest.to_string()));
+        }
+    }
+    if is_lowercase(comp_field(line)) {
+        return Err(AssemblerError::UnknownComp(comp_field(line).to_string()));
+    }
+    match jump {
+        Some(jump) if is_lowercase(jump) => Err(AssemblerError::UnknownJump(jump.to_string())),
+        _ => Ok(()),
+    }
+}
+
+// <dir>/<stem>.hack, whatever directories or dots the input name has
+fn output_file_for(filename: &str, dir: &Path) -> String {
+    let stem = Path::new(filename).file_stem().unwrap_or_default();
+    dir.join(format!("{}.hack", stem.to_string_lossy()))
+        .to_string_lossy()
+        .into_owned()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
     use std::path::Path;
 
+    // A fresh output directory for one test, outside the repository
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hack-assembler-test-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn new_should_return_an_io_error_when_the_source_file_is_missing() {
+        let result = HackAssembler::new("Missing.asm");
+        assert!(matches!(result, Err(AssemblerError::Io(_))));
+    }
+
     #[test]
     fn symbol_table_should_exists_and_contains_entries_with_sum1ton_file() {
-        let mut hack_assembler = HackAssembler::new("Sum1ToN.asm").unwrap();
+        let dir = temp_dir("sum1ton");
+        let mut hack_assembler = HackAssembler::with_output_dir("Sum1ToN.asm", &dir).unwrap();
         let _ = hack_assembler.execute();
-        assert_eq!(hack_assembler.symbol_table.contains("LOOP"), true);
-        assert_eq!(hack_assembler.symbol_table.contains("STOP"), true);
-        assert_eq!(hack_assembler.symbol_table.contains("i"), true);
-        assert_eq!(hack_assembler.symbol_table.contains("sum"), true);
-        assert_eq!(hack_assembler.symbol_table.get_address("LOOP"), Some(6));
-        assert_eq!(hack_assembler.symbol_table.get_address("STOP"), Some(21));
+        assert!(hack_assembler.symbol_table.contains("LOOP"));
+        assert!(hack_assembler.symbol_table.contains("STOP"));
+        assert!(hack_assembler.symbol_table.contains("i"));
+        assert!(hack_assembler.symbol_table.contains("sum"));
+        assert_eq!(hack_assembler.symbol_table.get_address("LOOP"), Some(4));
+        assert_eq!(hack_assembler.symbol_table.get_address("STOP"), Some(18));
         assert_eq!(hack_assembler.symbol_table.get_address("i"), Some(16));
-        assert_eq!(hack_assembler.symbol_table.get_address("sum"), Some(21));
+        assert_eq!(hack_assembler.symbol_table.get_address("sum"), Some(17));
+    }
+
+    #[test]
+    fn labels_should_resolve_to_rom_address_when_comments_and_blank_lines_are_interleaved() {
+        let dir = temp_dir("labels");
+        let mut hack_assembler = HackAssembler::with_output_dir("Labels.asm", &dir).unwrap();
+        let _ = hack_assembler.execute();
+        assert_eq!(hack_assembler.symbol_table.get_address("START"), Some(0));
+        assert_eq!(hack_assembler.symbol_table.get_address("LOOP"), Some(2));
+        assert_eq!(hack_assembler.symbol_table.get_address("END"), Some(5));
     }
 
     #[test]
     fn symbol_table_should_exists_with_test_file() {
-      let mut hack_assembler = HackAssembler::new("test.asm").unwrap();
+        let dir = temp_dir("test");
+        let mut hack_assembler = HackAssembler::with_output_dir("test.asm", &dir).unwrap();
         let _ = hack_assembler.execute();
-        assert_eq!(hack_assembler.symbol_table.contains("i"), true);
-        assert_eq!(hack_assembler.symbol_table.get_address("i"), Some(1));
-        assert_eq!(Path::new("hack-files/test.hack").exists(), true);
+        assert!(hack_assembler.symbol_table.contains("i"));
+        assert_eq!(hack_assembler.symbol_table.get_address("i"), Some(16));
+        assert!(dir.join("test.hack").exists());
     } 
 
     #[test]
     fn symbol_table_should_exists_with_add_file() {
-      let mut hack_assembler = HackAssembler::new("Add.asm").unwrap();
-      let _ = hack_assembler.execute();
-        assert_eq!(hack_assembler.symbol_table.contains("THIS"), true);
+        let dir = temp_dir("add");
+        let mut hack_assembler = HackAssembler::with_output_dir("Add.asm", &dir).unwrap();
+        let _ = hack_assembler.execute();
+        assert!(hack_assembler.symbol_table.contains("THIS"));
         assert_eq!(hack_assembler.symbol_table.get_address("THIS"), Some(3));
-        assert_eq!(Path::new("hack-files/Add.hack").exists(), true);
+        assert!(dir.join("Add.hack").exists());
     } 
+
+    #[test]
+    fn numeric_a_instructions_should_be_written_on_their_own_line() {
+        let dir = temp_dir("constants");
+        let mut hack_assembler = HackAssembler::with_output_dir("Constants.asm", &dir).unwrap();
+        let _ = hack_assembler.execute();
+        let output = fs::read_to_string(dir.join("Constants.hack")).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines, vec!["0000000000000101", "0000000000001010"]);
+        assert!(lines.iter().all(|line| line.len() == 16));
+    }
+
+    #[test]
+    fn executing_twice_should_not_append_a_second_copy_of_the_program() {
+        let dir = temp_dir("twice");
+        let mut hack_assembler = HackAssembler::with_output_dir("Max.asm", &dir).unwrap();
+        let _ = hack_assembler.execute();
+        let first_run = fs::read_to_string(dir.join("Max.hack")).unwrap();
+
+        let mut hack_assembler = HackAssembler::with_output_dir("Max.asm", &dir).unwrap();
+        let _ = hack_assembler.execute();
+        let second_run = fs::read_to_string(dir.join("Max.hack")).unwrap();
+
+        assert_eq!(first_run.lines().count(), 16);
+        assert_eq!(first_run, second_run);
+    }
+
+    #[test]
+    fn should_assemble_a_large_program_quickly() {
+        let source: String = (0..5_000).map(|i| format!("@{}\nD=A\n", i)).collect();
+
+        let start = std::time::Instant::now();
+        let mut hack_assembler = HackAssembler::from_source(&source);
+        let mut output: Vec<u8> = Vec::new();
+        let result = hack_assembler.write_output(&mut output);
+        let elapsed = start.elapsed();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(result.is_ok());
+        assert!(elapsed.as_secs() < 5);
+        assert_eq!(output.lines().count(), 10_000);
+        assert_eq!(output.lines().nth(9_998), Some("0001001110000111"));
+        assert_eq!(output.lines().nth(9_999), Some("1110110000010000"));
+    }
+
+    #[test]
+    fn should_assemble_screen_and_kbd_to_their_predefined_addresses() {
+        let dir = temp_dir("devices");
+        let mut hack_assembler = HackAssembler::with_output_dir("Devices.asm", &dir).unwrap();
+        let _ = hack_assembler.execute();
+        let output = fs::read_to_string(dir.join("Devices.hack")).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "0100000000000000");
+        assert_eq!(lines[2], "0110000000000000");
+        assert_eq!(hack_assembler.next_ram_address, 16);
+    }
+
+    #[test]
+    fn should_accept_the_largest_15_bit_constant() {
+        let dir = temp_dir("max-constant");
+        let mut hack_assembler = HackAssembler::with_output_dir("MaxConstant.asm", &dir).unwrap();
+        assert!(hack_assembler.execute().is_ok());
+        let output = fs::read_to_string(dir.join("MaxConstant.hack")).unwrap();
+        assert_eq!(output, "0111111111111111\n");
+    }
+
+    #[test]
+    fn should_reject_constants_above_32767() {
+        let mut hack_assembler =
+            HackAssembler::with_output_dir("LargeConstant.asm", temp_dir("large-constant"))
+                .unwrap();
+        let result = hack_assembler.execute();
+        assert!(matches!(
+            result,
+            Err(AssemblerError::AtLine { line: 1, error })
+                if matches!(*error, AssemblerError::ConstantOutOfRange(32768))
+        ));
+    }
+
+    #[test]
+    fn should_reject_negative_constants() {
+        let mut hack_assembler =
+            HackAssembler::with_output_dir("NegativeConstant.asm", temp_dir("negative-constant"))
+                .unwrap();
+        let result = hack_assembler.execute();
+        assert!(matches!(
+            result,
+            Err(AssemblerError::AtLine { line: 1, error })
+                if matches!(*error, AssemblerError::ConstantOutOfRange(-1))
+        ));
+    }
+
+    #[test]
+    fn should_fail_on_an_unknown_comp_instead_of_writing_a_short_instruction() {
+        let mut hack_assembler =
+            HackAssembler::with_output_dir("BadComp.asm", temp_dir("bad-comp")).unwrap();
+        let result = hack_assembler.execute();
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "line 2: unknown comp 'X+1'"
+        );
+    }
+
+    #[test]
+    fn should_report_a_label_defined_twice() {
+        let mut hack_assembler =
+            HackAssembler::with_output_dir("DuplicateLabel.asm", temp_dir("duplicate-label"))
+                .unwrap();
+        let result = hack_assembler.execute();
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "line 4: duplicate label 'END'"
+        );
+    }
+
+    #[test]
+    fn should_assemble_a_program_with_distinct_labels() {
+        let mut hack_assembler =
+            HackAssembler::with_output_dir("Labels.asm", temp_dir("distinct-labels")).unwrap();
+        assert!(hack_assembler.execute().is_ok());
+    }
+
+    #[test]
+    fn should_allocate_a_variable_referenced_before_any_label() {
+        let dir = temp_dir("forward-variable");
+        let mut hack_assembler =
+            HackAssembler::with_output_dir("ForwardVariable.asm", &dir).unwrap();
+        assert!(hack_assembler.execute().is_ok());
+        assert_eq!(hack_assembler.symbol_table.get_address("result"), Some(16));
+        let output = fs::read_to_string(dir.join("ForwardVariable.hack")).unwrap();
+        assert_eq!(output.lines().next(), Some("0000000000010000"));
+        assert_eq!(output.lines().count(), 4);
+    }
+
+    #[test]
+    fn should_resolve_a_label_used_before_it_is_defined() {
+        // @END is translated before the second pass reaches (END), so it must
+        // come from the first pass rather than allocate a variable
+        let mut hack_assembler =
+            HackAssembler::from_source("@END\nD;JGT\n@5\nD=A\n(END)\n@END\n0;JMP\n");
+        let words = hack_assembler.assemble_full().unwrap().0;
+        assert_eq!(words[0], 4);
+        assert_eq!(words[4], 4);
+        assert_eq!(
+            hack_assembler.symbol_table().get_kind("END"),
+            Some(SymbolKind::Label)
+        );
+        assert_eq!(hack_assembler.symbol_table().next_free_ram(), 16);
+    }
+
+    #[test]
+    fn assemble_str_should_return_the_machine_words_of_an_in_memory_program() {
+        let words = HackAssembler::assemble_str("@2\nD=A\n@3\nD=D+A").unwrap();
+        assert_eq!(
+            words,
+            vec![
+                0b0000000000000010,
+                0b1110110000010000,
+                0b0000000000000011,
+                0b1110000010010000,
+            ]
+        );
+    }
+
+    #[test]
+    fn assemble_to_writer_should_write_the_hack_text_into_any_writer() {
+        let mut hack_assembler = HackAssembler::new("Add.asm").unwrap();
+        let mut out: Vec<u8> = Vec::new();
+        hack_assembler.assemble_to_writer(&mut out).unwrap();
+        assert_eq!(
+            out,
+            b"0000000000000010\n\
+              1110110000010000\n\
+              0000000000000011\n\
+              1110000010010000\n\
+              0000000000000000\n\
+              1110001100001000\n"
+        );
+    }
+
+    #[test]
+    fn assemble_reader_should_assemble_the_source_read_from_any_reader() {
+        let reader = "@2\nD=A\n".as_bytes();
+        let words = HackAssembler::assemble_reader(reader).unwrap();
+        assert_eq!(words, vec![0b0000000000000010, 0b1110110000010000]);
+    }
+
+    #[test]
+    fn execute_should_create_the_output_directory_when_it_is_missing() {
+        let dir = temp_dir("missing-output-dir");
+        let output_file = dir.join("hack-files").join("Add.hack");
+
+        let mut hack_assembler =
+            HackAssembler::with_output_dir("Add.asm", dir.join("hack-files")).unwrap();
+        hack_assembler.execute().unwrap();
+
+        assert!(dir.join("hack-files").is_dir());
+        assert_eq!(fs::read_to_string(output_file).unwrap().lines().count(), 6);
+    }
+
+    #[test]
+    fn output_file_should_be_derived_from_the_file_stem() {
+        let dir = Path::new("hack-files");
+        assert_eq!(output_file_for("Add.asm", dir), "hack-files/Add.hack");
+        assert_eq!(
+            output_file_for("programs/Max.asm", dir),
+            "hack-files/Max.hack"
+        );
+        assert_eq!(
+            output_file_for("My.Game.asm", dir),
+            "hack-files/My.Game.hack"
+        );
+        assert_eq!(output_file_for("./rel/Add.asm", dir), "hack-files/Add.hack");
+        assert_eq!(
+            output_file_for("Add.asm", Path::new("out/bin")),
+            "out/bin/Add.hack"
+        );
+    }
+
+    #[test]
+    fn should_assemble_an_input_with_several_dots_in_its_name() {
+        let hack_assembler = HackAssembler::new("My.Game.asm").unwrap();
+        assert_eq!(hack_assembler.output_file, "hack-files/My.Game.hack");
+
+        let dir = temp_dir("several-dots");
+        let mut hack_assembler = HackAssembler::with_output_dir("My.Game.asm", &dir).unwrap();
+        hack_assembler.execute().unwrap();
+        let output = fs::read_to_string(dir.join("My.Game.hack")).unwrap();
+        assert_eq!(output.lines().count(), 2);
+    }
+
+    #[test]
+    fn write_listing_should_correlate_address_source_and_binary() {
+        let mut hack_assembler = HackAssembler::new("Add.asm").unwrap();
+        let mut listing: Vec<u8> = Vec::new();
+        hack_assembler.write_listing(&mut listing).unwrap();
+        assert_eq!(
+            String::from_utf8(listing).unwrap(),
+            "0000  @2  // 0000000000000010
+0001  D=A  // 1110110000010000
+0002  @3  // 0000000000000011
+0003  D=D+A  // 1110000010010000
+0004  @0  // 0000000000000000
+0005  M=D  // 1110001100001000
+"
+        );
+    }
+
+    #[test]
+    fn write_listing_should_annotate_labels_without_giving_them_an_address() {
+        let mut hack_assembler = HackAssembler::from_source("(LOOP)\n@LOOP\n0;JMP\n");
+        let mut listing: Vec<u8> = Vec::new();
+        hack_assembler.write_listing(&mut listing).unwrap();
+        assert_eq!(
+            String::from_utf8(listing).unwrap(),
+            "      (LOOP)
+0000  @LOOP  // 0000000000000000
+0001  0;JMP  // 1110101010000111
+"
+        );
+    }
+
+    #[test]
+    fn write_listing_should_name_the_predefined_address_of_an_a_instruction() {
+        let mut hack_assembler = HackAssembler::from_source("@KBD\nD=M\n@24576\n@R1\n");
+        let mut listing: Vec<u8> = Vec::new();
+        hack_assembler.write_listing(&mut listing).unwrap();
+        assert_eq!(
+            String::from_utf8(listing).unwrap(),
+            "0000  @KBD  // 0110000000000000  (KBD = 24576)
+0001  D=M  // 1111110000010000
+0002  @24576  // 0110000000000000
+0003  @R1  // 0000000000000001  (R1 = 1)
+"
+        );
+    }
+
+    #[test]
+    fn write_listing_should_not_name_a_predefined_symbol_a_label_shadows() {
+        let mut hack_assembler = HackAssembler::from_source("(SCREEN)\n@SCREEN\n0;JMP\n");
+        let mut listing: Vec<u8> = Vec::new();
+        hack_assembler.write_listing(&mut listing).unwrap();
+        assert!(String::from_utf8(listing)
+            .unwrap()
+            .contains("0000  @SCREEN  // 0000000000000000\n"));
+    }
+
+    #[test]
+    fn assemble_should_treat_a_label_on_an_instruction_line_as_its_own_line() {
+        let combined = HackAssembler::from_source("@i\n(LOOP) M=M+1\n@LOOP\n0;JMP\n")
+            .resolved_instructions()
+            .unwrap();
+        let separated = HackAssembler::from_source("@i\n(LOOP)\nM=M+1\n@LOOP\n0;JMP\n")
+            .resolved_instructions()
+            .unwrap();
+        assert_eq!(combined, separated);
+        assert_eq!(combined[2], (2, 1));
+    }
+
+    #[test]
+    fn resolved_instructions_should_pair_each_word_with_its_rom_address() {
+        let mut hack_assembler = HackAssembler::from_source("@i\nM=0\n(LOOP)\n@LOOP\n0;JMP\n");
+        assert_eq!(
+            hack_assembler.resolved_instructions().unwrap(),
+            [
+                (0, 0b0000000000010000),
+                (1, 0b1110101010001000),
+                (2, 0b0000000000000010),
+                (3, 0b1110101010000111),
+            ]
+        );
+    }
+
+    #[test]
+    fn execute_should_create_an_empty_output_for_a_program_without_instructions() {
+        let dir = temp_dir("empty-programs");
+
+        for (input, output) in [
+            ("Empty.asm", "Empty.hack"),
+            ("CommentsOnly.asm", "CommentsOnly.hack"),
+        ] {
+            let mut hack_assembler = HackAssembler::with_output_dir(input, &dir).unwrap();
+            assert_eq!(hack_assembler.execute().unwrap(), dir.join(output));
+            assert_eq!(fs::read_to_string(dir.join(output)).unwrap(), "");
+            assert_eq!(hack_assembler.stats(), AssemblyStats::default());
+        }
+    }
+
+    #[test]
+    fn dump_symbols_should_list_the_labels_and_variables_of_sum1ton() {
+        let mut hack_assembler = HackAssembler::new("Sum1ToN.asm").unwrap();
+        hack_assembler.assemble_to_writer(Vec::new()).unwrap();
+        let mut dump: Vec<u8> = Vec::new();
+        hack_assembler.dump_symbols(&mut dump).unwrap();
+        let dump = String::from_utf8(dump).unwrap();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert!(lines.contains(&"i 16 variable"));
+        assert!(lines.contains(&"sum 17 variable"));
+        assert!(lines.contains(&"LOOP 4 label"));
+        assert!(lines.contains(&"STOP 18 label"));
+        assert!(lines.contains(&"R0 0 predefined"));
+    }
+
+    #[test]
+    fn assemble_collecting_should_report_every_bad_line_in_one_run() {
+        let mut hack_assembler = HackAssembler::new("ThreeErrors.asm").unwrap();
+        let errors = hack_assembler.assemble_collecting().unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "line 1: constant 40000 is out of range 0..=32767",
+                "line 2: unknown comp 'X+1'",
+                "line 5: duplicate label 'LOOP'",
+            ]
+        );
+    }
+
+    #[test]
+    fn assemble_collecting_should_return_the_words_of_a_valid_program() {
+        let mut hack_assembler = HackAssembler::from_source("@2\nD=A\n");
+        assert_eq!(
+            hack_assembler.assemble_collecting().unwrap(),
+            [0b0000000000000010, 0b1110110000010000]
+        );
+    }
+
+    #[test]
+    fn errors_should_carry_the_source_line_past_comments_and_blank_lines() {
+        let result = HackAssembler::assemble_str("// Adds two numbers\n\n@2\nD=A\n\n@3\nD=D+X\n");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "line 7: unknown comp 'D+X'"
+        );
+    }
+
+    #[test]
+    fn should_fail_on_an_unknown_jump_instead_of_ignoring_it() {
+        let result = HackAssembler::assemble_str("@2\nD;JXX\n");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "line 2: unknown jump 'JXX'"
+        );
+    }
+
+    #[test]
+    fn should_assemble_lowercase_mnemonics_like_uppercase_ones() {
+        let lowercase = HackAssembler::assemble_str("@i\nd=m+1;jgt\nam=d|a\n0;jmp\n@I\n").unwrap();
+        let uppercase = HackAssembler::assemble_str("@i\nD=M+1;JGT\nAM=D|A\n0;JMP\n@I\n").unwrap();
+        assert_eq!(lowercase, uppercase);
+        // symbols stay case-sensitive, so i and I are two variables
+        assert_eq!(lowercase[0], 16);
+        assert_eq!(lowercase[4], 17);
+    }
+
+    #[test]
+    fn assemble_lines_should_return_one_binary_string_per_instruction() {
+        let mut hack_assembler = HackAssembler::from_source("@2\nD=A");
+        assert_eq!(
+            hack_assembler.assemble_lines().unwrap(),
+            ["0000000000000010", "1110110000010000"]
+        );
+    }
+
+    #[test]
+    fn assemble_binary_should_pack_each_word_big_endian() {
+        let mut hack_assembler = HackAssembler::from_source("@1\nD=A\n");
+        assert_eq!(
+            hack_assembler.assemble_binary().unwrap(),
+            [0x00, 0x01, 0b11101100, 0b00010000]
+        );
+    }
+
+    #[test]
+    fn assemble_hex_should_return_four_uppercase_digits_per_instruction() {
+        let mut hack_assembler = HackAssembler::from_source("@255\nD=A\n");
+        assert_eq!(hack_assembler.assemble_hex().unwrap(), ["00FF", "EC10"]);
+    }
+
+    #[test]
+    fn assemble_rust_array_should_emit_a_const_array_of_the_words() {
+        let mut hack_assembler = HackAssembler::from_source("@255\nD=A\n");
+        assert_eq!(
+            hack_assembler.assemble_rust_array("PROGRAM").unwrap(),
+            "pub const PROGRAM: [u16; 2] = [\n    0x00FF,\n    0xEC10,\n];\n"
+        );
+    }
+
+    #[test]
+    fn assemble_rust_array_should_compile_to_the_assembled_words() {
+        let source = fs::read_to_string("asm-files/Max.asm").unwrap();
+        let expected = HackAssembler::assemble_str(&source).unwrap();
+        let array = HackAssembler::from_source(&source)
+            .assemble_rust_array("MAX")
+            .unwrap();
+
+        let dir = temp_dir("rust-array");
+        fs::create_dir_all(&dir).unwrap();
+        let program = dir.join("max.rs");
+        fs::write(
+            &program,
+            format!(
+                "{}\nfn main() {{\n    println!(\"{{:?}}\", MAX);\n}}\n",
+                array
+            ),
+        )
+        .unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let status = std::process::Command::new(rustc)
+            .arg(&program)
+            .arg("-o")
+            .arg(dir.join("max"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let output = std::process::Command::new(dir.join("max"))
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap().trim(),
+            format!("{:?}", expected)
+        );
+    }
+
+    #[test]
+    fn assemble_rust_array_should_reject_a_name_that_is_not_an_identifier() {
+        for name in ["", "1ST", "MY-PROGRAM", "A.B"] {
+            assert!(matches!(
+                HackAssembler::from_source("@0\n").assemble_rust_array(name),
+                Err(AssemblerError::InvalidSymbol(symbol)) if symbol == name
+            ));
+        }
+    }
+
+    #[test]
+    fn should_assemble_a_file_in_a_subdirectory() {
+        let dir = temp_dir("subdir");
+        let mut hack_assembler = HackAssembler::with_output_dir("subdir/Subdir.asm", &dir).unwrap();
+        assert!(hack_assembler.execute().is_ok());
+        let output = fs::read_to_string(dir.join("Subdir.hack")).unwrap();
+        assert_eq!(
+            output,
+            "0000000000010000\n1110101010000111\n0000000000010001\n"
+        );
+    }
+
+    #[test]
+    fn both_passes_should_share_one_parser_through_reset() {
+        let mut hack_assembler = HackAssembler::new("Sum1ToN.asm").unwrap();
+        assert_eq!(hack_assembler.assemble_lines().unwrap().len(), 20);
+        assert_eq!(hack_assembler.symbol_table.get_address("LOOP"), Some(4));
+        assert_eq!(hack_assembler.symbol_table.get_address("STOP"), Some(18));
+        assert_eq!(hack_assembler.symbol_table.get_address("i"), Some(16));
+        assert_eq!(hack_assembler.symbol_table.get_address("sum"), Some(17));
+    }
+
+    #[test]
+    fn execute_should_return_the_path_it_wrote_to() {
+        let dir = temp_dir("returned-path");
+        let mut hack_assembler = HackAssembler::with_output_dir("Add.asm", &dir).unwrap();
+        let output_file = hack_assembler.execute().unwrap();
+        assert_eq!(output_file, dir.join("Add.hack"));
+        assert!(output_file.exists());
+    }
+
+    #[test]
+    fn with_output_dir_should_write_the_hack_file_into_the_given_directory() {
+        let dir = temp_dir("output-dir");
+
+        let mut hack_assembler = HackAssembler::with_output_dir("Max.asm", &dir).unwrap();
+        let output_file = hack_assembler.execute().unwrap();
+
+        assert_eq!(output_file, dir.join("Max.hack"));
+        assert_eq!(fs::read_to_string(output_file).unwrap().lines().count(), 16);
+    }
+
+    #[test]
+    fn assemble_streaming_should_match_the_buffered_api_on_a_large_program() {
+        // a few thousand loops, each with its own label and variable
+        let mut source = String::new();
+        for index in 0..4000 {
+            source.push_str(&format!(
+                "(LOOP{0})\n@counter{0}\nM=M+1\nD=M\n@LOOP{0}\nD;JGT\n@{0}\nD=A\n",
+                index
+            ));
+        }
+        let expected: String = HackAssembler::assemble_str(&source)
+            .unwrap()
+            .iter()
+            .map(|word| format!("{:016b}\n", word))
+            .collect();
+
+        let mut streamed: Vec<u8> = Vec::new();
+        HackAssembler::assemble_streaming(source.as_bytes(), &mut streamed).unwrap();
+        assert_eq!(String::from_utf8(streamed).unwrap(), expected);
+        assert_eq!(expected.lines().count(), 28000);
+    }
+
+    #[test]
+    fn write_sourcemap_should_map_each_address_to_its_source_line() {
+        let mut hack_assembler = HackAssembler::new("Add.asm").unwrap();
+        let mut sourcemap: Vec<u8> = Vec::new();
+        hack_assembler.write_sourcemap(&mut sourcemap).unwrap();
+        let sourcemap = String::from_utf8(sourcemap).unwrap();
+        let entries: Vec<&str> = sourcemap.lines().collect();
+        assert_eq!(entries.len(), 8);
+        assert_eq!(entries[1], "  {\"address\": 0, \"line\": 7},");
+        assert_eq!(entries[6], "  {\"address\": 5, \"line\": 12}");
+    }
+
+    #[test]
+    fn write_sourcemap_should_skip_labels_and_comments() {
+        let mut hack_assembler = HackAssembler::from_source("// start\n(LOOP)\n@LOOP\n\n0;JMP\n");
+        let mut sourcemap: Vec<u8> = Vec::new();
+        hack_assembler.write_sourcemap(&mut sourcemap).unwrap();
+        assert_eq!(
+            String::from_utf8(sourcemap).unwrap(),
+            "[\n  {\"address\": 0, \"line\": 3},\n  {\"address\": 1, \"line\": 5}\n]\n"
+        );
+    }
+
+    #[test]
+    fn assemble_bytes_should_assemble_ascii_source() {
+        assert_eq!(
+            HackAssembler::assemble_bytes(b"@2\nD=A\n").unwrap(),
+            HackAssembler::assemble_str("@2\nD=A\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn assemble_bytes_should_reject_invalid_utf8() {
+        let error = HackAssembler::assemble_bytes(b"@2\n\xff\xfe\n").unwrap_err();
+        assert!(matches!(error, AssemblerError::InvalidUtf8(_)));
+        assert_eq!(
+            error.to_string(),
+            "source is not valid UTF-8: invalid utf-8 sequence of 1 bytes from index 3"
+        );
+    }
+
+    #[test]
+    fn strict_mode_should_reject_a_label_named_like_a_predefined_symbol() {
+        let source = "(SCREEN)\n@SCREEN\n0;JMP\n";
+        let options = AssemblerOptions {
+            strict: true,
+            ..AssemblerOptions::default()
+        };
+        let mut hack_assembler = HackAssembler::from_source_with_options(source, options);
+        assert_eq!(
+            hack_assembler.assemble_lines().unwrap_err().to_string(),
+            "line 1: 'SCREEN' is a predefined symbol"
+        );
+    }
+
+    #[test]
+    fn lenient_mode_should_warn_about_a_label_named_like_a_predefined_symbol() {
+        let mut hack_assembler = HackAssembler::from_source("@0\n(SCREEN)\n@SCREEN\n0;JMP\n");
+        assert_eq!(
+            hack_assembler.assemble_lines().unwrap()[1],
+            "0000000000000001"
+        );
+        assert_eq!(
+            hack_assembler.warnings(),
+            [AssemblerWarning::ShadowedPredefined {
+                line: 2,
+                label: "SCREEN".to_string()
+            }]
+        );
+        assert_eq!(
+            hack_assembler.warnings()[0].to_string(),
+            "line 2: label 'SCREEN' shadows a predefined symbol"
+        );
+    }
+
+    #[test]
+    fn assemble_full_should_return_the_words_and_the_symbol_table() {
+        let mut hack_assembler = HackAssembler::new("Sum1ToN.asm").unwrap();
+        let (words, symbol_table) = hack_assembler.assemble_full().unwrap();
+
+        let source = fs::read_to_string("asm-files/Sum1ToN.asm").unwrap();
+        assert_eq!(words, HackAssembler::assemble_str(&source).unwrap());
+        assert_eq!(words.len(), 20);
+        assert_eq!(symbol_table.get_address("LOOP"), Some(4));
+        assert_eq!(symbol_table.get_address("i"), Some(16));
+    }
+
+    #[test]
+    fn variable_addresses_should_depend_only_on_first_use_order() {
+        let compact = "@sum\nM=0\n@i\nM=1\n(LOOP)\n@count\nM=M+1\n@LOOP\n0;JMP\n";
+        let commented = "// totals
+
+
+@sum // first
+M=0
+
+// counter
+@i
+M=1
+(LOOP)
+  // bump
+
+  @count
+  M=M+1
+  @LOOP
+  0;JMP
+";
+        let mut compact = HackAssembler::from_source(compact);
+        let mut commented = HackAssembler::from_source(commented);
+        assert_eq!(
+            compact.assemble_lines().unwrap(),
+            commented.assemble_lines().unwrap()
+        );
+        for (variable, address) in [("sum", 16), ("i", 17), ("count", 18)] {
+            assert_eq!(compact.symbol_table().get_address(variable), Some(address));
+            assert_eq!(
+                commented.symbol_table().get_address(variable),
+                Some(address)
+            );
+        }
+    }
+
+    #[test]
+    fn symbol_table_should_report_the_next_free_ram_address() {
+        let mut hack_assembler = HackAssembler::from_source("@a\nM=0\n@b\nM=0\n@c\nM=0\n@a\n");
+        hack_assembler.assemble_lines().unwrap();
+        assert_eq!(hack_assembler.symbol_table().next_free_ram(), 19);
+    }
+
+    #[test]
+    fn should_reject_a_program_larger_than_the_rom() {
+        let options = AssemblerOptions {
+            rom_limit: 4,
+            ..AssemblerOptions::default()
+        };
+        let mut fits = HackAssembler::from_source_with_options(
+            "@1\nD=A\n(END)\n@END\n0;JMP\n",
+            options.clone(),
+        );
+        assert_eq!(fits.assemble_lines().unwrap().len(), 4);
+        let mut too_large =
+            HackAssembler::from_source_with_options("@1\nD=A\n@2\nD=D+A\n@0\n", options);
+        assert!(matches!(
+            too_large.assemble_lines(),
+            Err(AssemblerError::ProgramTooLarge(5))
+        ));
+    }
+
+    #[test]
+    fn should_reject_a_variable_past_the_configured_ram() {
+        let options = AssemblerOptions {
+            max_ram: 18,
+            ..AssemblerOptions::default()
+        };
+        let mut fits = HackAssembler::from_source_with_options("@a\n@b\n@a\n", options.clone());
+        assert!(fits.assemble_lines().is_ok());
+        let mut too_many = HackAssembler::from_source_with_options("@a\n@b\n@c\n", options);
+        let error = too_many.assemble_lines().unwrap_err();
+        assert_eq!(error.to_string(), "line 3: no RAM left for variable 'c'");
+    }
+
+    #[test]
+    fn should_reject_a_max_ram_past_what_an_a_instruction_can_address() {
+        let options = AssemblerOptions {
+            max_ram: 40000,
+            ..AssemblerOptions::default()
+        };
+        let mut hack_assembler = HackAssembler::from_source_with_options("@a\n", options);
+        assert!(matches!(
+            hack_assembler.assemble_lines(),
+            Err(AssemblerError::MaxRamTooLarge(40000))
+        ));
+    }
+
+    #[test]
+    fn should_reject_a_symbol_whose_address_does_not_fit_an_a_instruction() {
+        let options = AssemblerOptions {
+            rom_limit: 40000,
+            ..AssemblerOptions::default()
+        };
+        let source = format!("{}(FAR)\n@FAR\n", "@0\n".repeat(32768));
+        let mut hack_assembler = HackAssembler::from_source_with_options(&source, options);
+        assert_eq!(
+            hack_assembler.assemble_lines().unwrap_err().to_string(),
+            "line 32770: 'FAR' has address 32768, past 32767"
+        );
+    }
+
+    #[test]
+    fn assemble_to_writer_should_annotate_each_word_with_its_instruction() {
+        let options = AssemblerOptions {
+            annotate: true,
+            ..AssemblerOptions::default()
+        };
+        let mut hack_assembler =
+            HackAssembler::from_source_with_options("@2\nD=A // copy\n", options);
+        let mut output: Vec<u8> = Vec::new();
+        hack_assembler.assemble_to_writer(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "0000000000000010 // @2\n1110110000010000 // D=A\n"
+        );
+    }
+
+    #[test]
+    fn undefined_symbols_should_report_a_jump_to_a_missing_label() {
+        let source = "@i\nM=0\n(LOOP)\n@i\nD=M\n@LOOP\nD;JGT\n@ENDD\n0;JMP\n(END)\n@END\n0;JMP\n";
+        let mut hack_assembler = HackAssembler::from_source(source);
+        assert_eq!(
+            hack_assembler.undefined_symbols().unwrap(),
+            [("ENDD".to_string(), 8)]
+        );
+        assert_eq!(
+            HackAssembler::new("Sum1ToN.asm")
+                .unwrap()
+                .undefined_symbols()
+                .unwrap(),
+            []
+        );
+    }
+
+    #[test]
+    fn undefined_symbols_should_not_report_a_variable_read_before_a_jump() {
+        let source = "(LOOP)\n@x\nD=M\n@x\n0;JMP\n@ptr\nA=M\n0;JMP\n";
+        let mut hack_assembler = HackAssembler::from_source(source);
+        assert_eq!(hack_assembler.undefined_symbols().unwrap(), []);
+    }
+
+    #[test]
+    fn undefined_symbols_should_follow_a_until_the_jump() {
+        let source = "@ENDD\nD=A\n0;JMP\n@END\nD=D+1\nA=D\n0;JMP\n";
+        let mut hack_assembler = HackAssembler::from_source(source);
+        assert_eq!(
+            hack_assembler.undefined_symbols().unwrap(),
+            [("ENDD".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn with_symbols_should_resolve_preloaded_symbols() {
+        let mut symbols = SymbolTable::new();
+        symbols.add_entry("VIDEO", 20000, SymbolKind::Predefined);
+        let mut hack_assembler = HackAssembler::with_symbols("Video.asm", symbols).unwrap();
+        assert_eq!(
+            hack_assembler.assemble_full().unwrap().0,
+            [20000, 0b1110111010001000]
+        );
+        assert!(hack_assembler.undefined_symbols().unwrap().is_empty());
+        assert_eq!(hack_assembler.symbol_table().next_free_ram(), 16);
+    }
+
+    #[test]
+    fn with_symbols_should_allocate_variables_past_the_preloaded_ones() {
+        let mut symbols = SymbolTable::new();
+        symbols.add_entry("VIDEO", 16, SymbolKind::Variable);
+        let mut hack_assembler = HackAssembler::with_symbols("Sum1ToN.asm", symbols).unwrap();
+        hack_assembler.assemble_lines().unwrap();
+        assert_eq!(hack_assembler.symbol_table().get_address("i"), Some(17));
+        assert_eq!(hack_assembler.symbol_table().get_address("sum"), Some(18));
+        assert_eq!(hack_assembler.stats().variables, 2);
+    }
+
+    #[test]
+    fn with_symbols_should_not_count_preloaded_variables_in_the_stats() {
+        let mut symbols = SymbolTable::new();
+        symbols.add_entry("buffer", 16, SymbolKind::Variable);
+        symbols.add_entry("count", 17, SymbolKind::Variable);
+        let mut hack_assembler = HackAssembler::with_symbols("Add.asm", symbols).unwrap();
+        hack_assembler.assemble_lines().unwrap();
+        assert_eq!(hack_assembler.stats().variables, 0);
+    }
+
+    #[test]
+    fn with_symbols_should_reject_an_address_past_32767() {
+        let mut symbols = SymbolTable::new();
+        symbols.add_entry("far", 32768, SymbolKind::Variable);
+        let error = HackAssembler::with_symbols("Add.asm", symbols)
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "'far' has address 32768, past 32767");
+    }
+
+    #[test]
+    fn assemble_padded_should_fill_the_rom_image_with_zeros() {
+        let mut hack_assembler = HackAssembler::new("Max.asm").unwrap();
+        let words = hack_assembler.assemble_full().unwrap().0;
+        let padded = hack_assembler.assemble_padded(20).unwrap();
+        assert_eq!(padded[..16], words[..]);
+        assert_eq!(padded[16..], [0, 0, 0, 0]);
+        assert!(matches!(
+            hack_assembler.assemble_padded(15),
+            Err(AssemblerError::ProgramTooLarge(16))
+        ));
+    }
+
+    #[test]
+    fn write_output_should_pad_each_format_to_the_requested_size() {
+        let source = "@2\nD=A\n@3\nD=D+A\n";
+        for (format, expected) in [
+            (OutputFormat::Text, "0000000000000000\n".repeat(4)),
+            (OutputFormat::Hex, "0000\n".repeat(4)),
+            (OutputFormat::Binary, "\0\0".repeat(4)),
+        ] {
+            let options = AssemblerOptions {
+                format,
+                pad: Some(8),
+                ..AssemblerOptions::default()
+            };
+            let mut output: Vec<u8> = Vec::new();
+            HackAssembler::from_source_with_options(source, options)
+                .write_output(&mut output)
+                .unwrap();
+            let padding = output.len() / 2;
+            assert_eq!(String::from_utf8_lossy(&output[padding..]), expected);
+        }
+    }
+
+    #[test]
+    fn should_skip_semicolon_comments_when_enabled() {
+        let options = AssemblerOptions {
+            semicolon_comments: true,
+            ..AssemblerOptions::default()
+        };
+        let mut hack_assembler = HackAssembler::from_source_with_options(
+            "; loop forever\n(END)\n@END\n0;JMP\n",
+            options,
+        );
+        assert_eq!(
+            hack_assembler.assemble_lines().unwrap(),
+            ["0000000000000000", "1110101010000111"]
+        );
+    }
+
+    #[test]
+    fn reset_for_should_assemble_each_file_like_a_fresh_instance() {
+        let files = ["Max.asm", "Sum1ToN.asm", "Labels.asm", "Add.asm"];
+        let mut reused = HackAssembler::new(files[0]).unwrap();
+        for file in files {
+            reused.reset_for(file).unwrap();
+            let mut fresh = HackAssembler::new(file).unwrap();
+            assert_eq!(
+                reused.assemble_lines().unwrap(),
+                fresh.assemble_lines().unwrap()
+            );
+            assert_eq!(reused.symbol_table(), fresh.symbol_table());
+            assert_eq!(reused.stats(), fresh.stats());
+        }
+    }
+
+    #[test]
+    fn should_assemble_the_same_way_on_every_run() {
+        let source = "@0\n(SCREEN)\n@SCREEN\n0;JMP\n(NEVER)\n@0\n";
+        let mut hack_assembler = HackAssembler::from_source(source);
+        let first = hack_assembler.assemble_lines().unwrap();
+        let warnings = hack_assembler.warnings().to_vec();
+        assert_eq!(
+            warnings,
+            [
+                AssemblerWarning::ShadowedPredefined {
+                    line: 2,
+                    label: "SCREEN".to_string()
+                },
+                AssemblerWarning::UnusedLabel {
+                    line: 5,
+                    label: "NEVER".to_string()
+                },
+            ]
+        );
+        assert_eq!(hack_assembler.assemble_lines().unwrap(), first);
+        assert_eq!(hack_assembler.warnings(), warnings);
+    }
+
+    #[test]
+    fn reset_for_should_keep_preloaded_symbols_and_restore_shadowed_ones() {
+        let mut symbols = SymbolTable::new();
+        symbols.add_entry("VIDEO", 20000, SymbolKind::Predefined);
+        symbols.add_entry("buffer", 16, SymbolKind::Variable);
+        let mut hack_assembler =
+            HackAssembler::with_symbols("ShadowedScreen.asm", symbols).unwrap();
+        hack_assembler.assemble_lines().unwrap();
+        assert_eq!(
+            hack_assembler.symbol_table().get_kind("SCREEN"),
+            Some(SymbolKind::Label)
+        );
+
+        hack_assembler.reset_for("Devices.asm").unwrap();
+        assert_eq!(
+            hack_assembler.symbol_table().get_kind("SCREEN"),
+            Some(SymbolKind::Predefined)
+        );
+        assert_eq!(
+            hack_assembler.assemble_lines().unwrap()[0],
+            "0100000000000000"
+        );
+        assert_eq!(
+            hack_assembler.symbol_table().get_address("VIDEO"),
+            Some(20000)
+        );
+
+        hack_assembler.reset_for("Sum1ToN.asm").unwrap();
+        hack_assembler.assemble_lines().unwrap();
+        assert_eq!(hack_assembler.symbol_table().get_address("i"), Some(17));
+    }
+
+    #[test]
+    fn diff_hack_should_return_nothing_for_matching_programs() {
+        let words =
+            HackAssembler::assemble_str(&fs::read_to_string("asm-files/Max.asm").unwrap()).unwrap();
+        assert_eq!(diff_hack(&words, &words), []);
+    }
+
+    #[test]
+    fn diff_hack_should_return_each_address_that_differs() {
+        let reference = HackAssembler::assemble_str("@2\nD=A\n@3\nD=D+A\n").unwrap();
+        let submitted = HackAssembler::assemble_str("@2\nD=A\n@4\nD=D+A\n").unwrap();
+        assert_eq!(diff_hack(&submitted, &reference), [(2, 4, 3)]);
+    }
+
+    #[test]
+    fn parse_hack_should_read_back_what_was_assembled() {
+        let mut hack_assembler = HackAssembler::new("Sum1ToN.asm").unwrap();
+        let text = hack_assembler.assemble_lines().unwrap().join("\n");
+        let words =
+            HackAssembler::assemble_str(&fs::read_to_string("asm-files/Sum1ToN.asm").unwrap())
+                .unwrap();
+        assert_eq!(parse_hack(&text).unwrap(), words);
+        assert!(matches!(
+            parse_hack("0000000000000010\n12\n"),
+            Err(AssemblerError::AtLine { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn execute_should_leave_the_previous_output_untouched_when_assembly_fails() {
+        let dir = temp_dir("atomic-output");
+        fs::create_dir_all(&dir).unwrap();
+        let output_file = dir.join("BadComp.hack");
+        fs::write(&output_file, "0000000000000111\n").unwrap();
+
+        let mut hack_assembler = HackAssembler::with_output_dir("BadComp.asm", &dir).unwrap();
+        assert!(hack_assembler.execute().is_err());
+
+        assert_eq!(
+            fs::read_to_string(&output_file).unwrap(),
+            "0000000000000111\n"
+        );
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn write_atomically_should_replace_the_file_and_leave_no_temporary() {
+        let dir = temp_dir("write-atomically");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Out.hack");
+        fs::write(&path, "old").unwrap();
+
+        write_atomically(&path, b"new").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn should_reject_a_constant_with_a_plus_sign() {
+        assert_eq!(
+            HackAssembler::assemble_str("@+5\n")
+                .unwrap_err()
+                .to_string(),
+            "line 1: invalid symbol '+5'"
+        );
+    }
+
+    #[test]
+    fn should_assemble_hex_and_binary_constants() {
+        assert_eq!(
+            HackAssembler::assemble_str("@0xFF\n@0b100000000\n@255\n").unwrap(),
+            [255, 256, 255]
+        );
+        assert_eq!(
+            HackAssembler::assemble_str("@0xFFFF\n")
+                .unwrap_err()
+                .to_string(),
+            "line 1: constant 65535 is out of range 0..=32767"
+        );
+        assert_eq!(
+            HackAssembler::assemble_str("#define MASK 0x7FFF\n@MASK\n").unwrap(),
+            [32767]
+        );
+    }
+
+    #[test]
+    fn stats_should_count_the_instructions_of_sum1ton() {
+        let mut hack_assembler = HackAssembler::new("Sum1ToN.asm").unwrap();
+        hack_assembler.assemble_lines().unwrap();
+        assert_eq!(
+            hack_assembler.stats(),
+            AssemblyStats {
+                a_instructions: 10,
+                c_instructions: 10,
+                labels: 2,
+                variables: 2,
+                rom_words: 20,
+            }
+        );
+        assert_eq!(
+            hack_assembler.stats().to_string(),
+            "10 A-instructions, 10 C-instructions, 2 labels, 2 variables, 20 ROM words"
+        );
+    }
+
+    #[test]
+    fn stats_to_json_should_key_each_count_by_its_field() {
+        let mut hack_assembler = HackAssembler::new("Sum1ToN.asm").unwrap();
+        hack_assembler.assemble_lines().unwrap();
+        assert_eq!(
+            hack_assembler.stats().to_json(),
+            "{\"a_instructions\": 10, \"c_instructions\": 10, \"labels\": 2, \"variables\": 2, \"rom_words\": 20}"
+        );
+    }
+
+    #[test]
+    fn check_should_validate_without_writing_the_output_file() {
+        let dir = temp_dir("check");
+
+        let mut hack_assembler = HackAssembler::with_output_dir("Add.asm", &dir).unwrap();
+        assert!(hack_assembler.check().is_ok());
+        assert!(!dir.join("Add.hack").exists());
+
+        let mut hack_assembler = HackAssembler::with_output_dir("ThreeErrors.asm", &dir).unwrap();
+        assert_eq!(hack_assembler.check().unwrap_err().len(), 3);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn with_output_dir_should_accept_the_input_directory() {
+        let hack_assembler = HackAssembler::with_output_dir("Max.asm", "asm-files").unwrap();
+        assert_eq!(hack_assembler.output_file, "asm-files/Max.hack");
+    }
+
+    #[test]
+    fn should_reject_an_invalid_label_or_constant_name_in_either_mode() {
+        for strict in [false, true] {
+            let options = AssemblerOptions {
+                strict,
+                ..AssemblerOptions::default()
+            };
+            let assemble = |source: &str| {
+                HackAssembler::from_source_with_options(source, options.clone())
+                    .assemble_lines()
+                    .unwrap_err()
+                    .to_string()
+            };
+            assert_eq!(
+                assemble("(LOOP-END)\n@0\n0;JMP\n"),
+                "line 1: invalid symbol '(LOOP-END)'"
+            );
+            assert_eq!(assemble("(9abc)\n"), "line 1: invalid symbol '(9abc)'");
+            assert_eq!(
+                assemble("#define 9N 3\n@0\n"),
+                "line 1: invalid symbol '9N'"
+            );
+        }
+    }
+
+    #[test]
+    fn should_reject_an_unclosed_label_in_either_mode() {
+        for strict in [false, true] {
+            let options = AssemblerOptions {
+                strict,
+                ..AssemblerOptions::default()
+            };
+            let mut hack_assembler =
+                HackAssembler::from_source_with_options("(LOOP\n@LOOP\n0;JMP\n", options);
+            assert_eq!(
+                hack_assembler.assemble_lines().unwrap_err().to_string(),
+                "line 1: malformed line '(LOOP'"
+            );
+        }
+    }
+
+    #[test]
+    fn should_reject_an_empty_label_or_a_lone_paren_in_either_mode() {
+        for strict in [false, true] {
+            let options = AssemblerOptions {
+                strict,
+                ..AssemblerOptions::default()
+            };
+            let assemble = |source: &str| {
+                HackAssembler::from_source_with_options(source, options.clone())
+                    .assemble_lines()
+                    .unwrap_err()
+                    .to_string()
+            };
+            assert_eq!(assemble("(\n@0\n"), "line 1: malformed line '('");
+            assert_eq!(assemble("@0\n()\n"), "line 2: invalid symbol '()'");
+        }
+    }
+
+    #[test]
+    fn strict_mode_should_reject_a_symbol_starting_with_a_digit() {
+        let options = AssemblerOptions {
+            strict: true,
+            ..AssemblerOptions::default()
+        };
+        let mut hack_assembler = HackAssembler::from_source_with_options("@1abc\n", options);
+        assert_eq!(
+            hack_assembler.assemble_lines().unwrap_err().to_string(),
+            "line 1: invalid symbol '1abc'"
+        );
+    }
+
+    #[test]
+    fn should_assemble_a_last_instruction_without_a_trailing_newline() {
+        let source = fs::read_to_string("asm-files/NoTrailingNewline.asm").unwrap();
+        assert!(!source.ends_with('\n'));
+        let words = HackAssembler::assemble_str(&source).unwrap();
+        assert_eq!(words.len(), 6);
+        assert_eq!(words.last(), Some(&0b1110001100001000));
+        assert_eq!(
+            HackAssembler::assemble_reader(source.as_bytes()).unwrap(),
+            words
+        );
+    }
+
+    #[test]
+    fn crlf_source_should_assemble_like_lf_source() {
+        let lf = fs::read_to_string("asm-files/Max.asm")
+            .unwrap()
+            .replace("\r\n", "\n");
+        let crlf = lf.replace('\n', "\r\n");
+        let options = AssemblerOptions {
+            strict: true,
+            ..AssemblerOptions::default()
+        };
+        let assemble = |source: &str| {
+            HackAssembler::from_source_with_options(source, options.clone())
+                .assemble_lines()
+                .unwrap()
+        };
+        assert_eq!(assemble(&crlf), assemble(&lf));
+    }
+
+    #[test]
+    fn verify_against_should_accept_the_matching_output() {
+        let mut hack_assembler = HackAssembler::new("Add.asm").unwrap();
+        let expected = "0000000000000010
+1110110000010000
+0000000000000011
+1110000010010000
+0000000000000000
+1110001100001000
+";
+        assert_eq!(hack_assembler.verify_against(expected).unwrap(), None);
+    }
+
+    #[test]
+    fn verify_against_should_return_the_first_differing_line() {
+        let mut hack_assembler = HackAssembler::from_source("@2\nD=A\n@3\n");
+        let altered = "0000000000000010\n1110110000010001\n0000000000000011\n";
+        assert_eq!(hack_assembler.verify_against(altered).unwrap(), Some(2));
+        let truncated = "0000000000000010\n1110110000010000\n";
+        assert_eq!(hack_assembler.verify_against(truncated).unwrap(), Some(3));
+    }
+
+    #[test]
+    fn should_reject_a_c_instruction_without_a_comp() {
+        assert_eq!(
+            HackAssembler::assemble_str("@1\nD=\n")
+                .unwrap_err()
+                .to_string(),
+            "line 2: missing comp in 'D='"
+        );
+        assert_eq!(
+            HackAssembler::assemble_str(";JMP\n")
+                .unwrap_err()
+                .to_string(),
+            "line 1: missing comp in ';JMP'"
+        );
+        assert_eq!(
+            HackAssembler::assemble_str("0;JMP\n").unwrap(),
+            [0b1110101010000111]
+        );
+    }
+
+    #[test]
+    fn should_encode_a_bare_comp_with_no_dest_and_no_jump() {
+        let mut hack_assembler = HackAssembler::from_source("D+1\n");
+        // 111 0 011111 000 000: the result is computed and discarded
+        assert_eq!(
+            hack_assembler.assemble_lines().unwrap(),
+            ["1110011111000000"]
+        );
+    }
+
+    #[test]
+    fn should_reject_a_symbol_that_breaks_the_hack_lexical_rules() {
+        assert!(HackAssembler::assemble_str("@loop.end\n").is_ok());
+        for (source, symbol) in [("@9abc\n", "9abc"), ("@a b\n", "a b")] {
+            assert_eq!(
+                HackAssembler::assemble_str(source).unwrap_err().to_string(),
+                format!("line 1: invalid symbol '{}'", symbol)
+            );
+        }
+    }
+
+    #[test]
+    fn lowercase_mnemonics_should_fail_when_they_are_not_allowed() {
+        let options = AssemblerOptions {
+            allow_lowercase: false,
+            ..AssemblerOptions::default()
+        };
+        let mut hack_assembler = HackAssembler::from_source_with_options("@i\nD=m+1\n", options);
+        assert_eq!(
+            hack_assembler.assemble_lines().unwrap_err().to_string(),
+            "line 2: unknown comp 'm+1'"
+        );
+    }
+
+    #[test]
+    fn with_options_should_write_the_configured_format_into_the_configured_dir() {
+        let dir = temp_dir("with-options");
+        let options = AssemblerOptions {
+            format: OutputFormat::Hex,
+            output_dir: dir.clone(),
+            ..AssemblerOptions::default()
+        };
+
+        let mut hack_assembler = HackAssembler::with_options("Add.asm", options).unwrap();
+        let output_file = hack_assembler.execute().unwrap();
+
+        assert_eq!(output_file, dir.join("Add.hack"));
+        assert_eq!(
+            fs::read_to_string(output_file).unwrap(),
+            "0002\nEC10\n0003\nE090\n0000\nE308\n"
+        );
+    }
+
+    #[test]
+    fn strict_mode_should_reject_a_malformed_line_that_lenient_mode_skips() {
+        let source = "@2\nD=A\ngibberish\n@3\n";
+        assert_eq!(
+            HackAssembler::assemble_str(source).unwrap(),
+            [2, 0b1110110000010000, 3]
+        );
+
+        let options = AssemblerOptions {
+            strict: true,
+            ..AssemblerOptions::default()
+        };
+        let mut hack_assembler = HackAssembler::from_source_with_options(source, options);
+        assert_eq!(
+            hack_assembler.assemble_lines().unwrap_err().to_string(),
+            "line 3: malformed line 'gibberish'"
+        );
+    }
+
+    #[test]
+    fn should_warn_about_a_label_that_is_never_used() {
+        let mut hack_assembler =
+            HackAssembler::from_source("(START)\n@START\n0;JMP\n(NEVER)\n@0\n");
+        hack_assembler.assemble_lines().unwrap();
+        let warnings: Vec<String> = hack_assembler
+            .warnings()
+            .iter()
+            .map(|warning| warning.to_string())
+            .collect();
+        assert_eq!(warnings, ["line 4: label 'NEVER' is never used"]);
+    }
+
+    #[test]
+    fn should_not_warn_when_every_label_is_used() {
+        let mut hack_assembler = HackAssembler::new("Sum1ToN.asm").unwrap();
+        hack_assembler.assemble_lines().unwrap();
+        assert!(hack_assembler.warnings().is_empty());
+    }
+
+    #[test]
+    fn should_assemble_shifts_only_with_the_extended_alu() {
+        let source = "D=D<<\nAM=M>>\n";
+        assert_eq!(
+            HackAssembler::assemble_str(source).unwrap_err().to_string(),
+            "line 1: unknown comp 'D<<'"
+        );
+
+        let options = AssemblerOptions {
+            extended_alu: true,
+            ..AssemblerOptions::default()
+        };
+        let mut hack_assembler = HackAssembler::from_source_with_options(source, options);
+        assert_eq!(
+            hack_assembler.assemble_lines().unwrap(),
+            ["1010110000010000", "1011000000101000"]
+        );
+    }
+
+    #[test]
+    fn should_substitute_a_defined_constant_without_taking_ram() {
+        let mut hack_assembler =
+            HackAssembler::from_source("#define WIDTH 512\n@WIDTH\nD=A\n@i\nM=D\n");
+        assert_eq!(
+            hack_assembler.assemble_lines().unwrap(),
+            [
+                "0000001000000000",
+                "1110110000010000",
+                "0000000000010000",
+                "1110001100001000",
+            ]
+        );
+        // i still gets the first variable address
+        assert_eq!(hack_assembler.symbol_table().get_address("i"), Some(16));
+    }
+
+    #[test]
+    fn should_classify_labels_and_variables_in_the_symbol_table() {
+        let source = fs::read_to_string("asm-files/Sum1ToN.asm").unwrap();
+        let mut hack_assembler = HackAssembler::from_source(&source);
+        hack_assembler.assemble_lines().unwrap();
+        let symbol_table = hack_assembler.symbol_table();
+        assert_eq!(symbol_table.get_kind("i"), Some(SymbolKind::Variable));
+        assert_eq!(symbol_table.get_kind("LOOP"), Some(SymbolKind::Label));
+        assert_eq!(symbol_table.get_kind("R0"), Some(SymbolKind::Predefined));
+    }
+
+    #[test]
+    fn should_reject_a_constant_defined_twice() {
+        let result = HackAssembler::assemble_str("#define N 1\n#define N 2\n@N\n");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "line 2: duplicate symbol 'N'"
+        );
+    }
 }
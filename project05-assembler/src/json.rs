@@ -0,0 +1,31 @@
+/// `text` as a quoted JSON string, with quotes, backslashes and control
+/// characters escaped, for the JSON the assembler and its CLI write.
+pub fn json_string(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_string_should_quote_plain_text() {
+        assert_eq!(json_string("asm-files/Add.asm"), r#""asm-files/Add.asm""#);
+    }
+
+    #[test]
+    fn json_string_should_escape_quotes_backslashes_and_control_characters() {
+        assert_eq!(json_string(r#"a"b\c"#), r#""a\"b\\c""#);
+        assert_eq!(json_string("a\nb\u{7f}"), r#""a\u000ab\u007f""#);
+    }
+}
@@ -1,3 +1,11 @@
+pub mod disassembler;
+pub mod error;
+pub mod formatter;
 pub mod hack_assembler;
+pub mod json;
+pub mod lint;
+pub mod options;
 pub mod parser;
 pub mod symbol_table;
+#[cfg(feature = "wasm")]
+pub mod wasm;
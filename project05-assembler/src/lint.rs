@@ -0,0 +1,153 @@
+use std::fmt;
+
+use crate::{
+    error::AssemblerWarning,
+    parser::{comp_field, InstructionType, Parser},
+    symbol_table::{SymbolKind, SymbolTable},
+};
+
+/// A suspicious pattern that assembles fine but is likely a mistake.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Lint {
+    /// An A-instruction whose value is overwritten by the A-instruction right
+    /// after it, before anything reads it.
+    UselessAInstruction { line: usize, symbol: String },
+    /// A conditional jump on a constant comp such as `0;JEQ`, whose outcome
+    /// never depends on a computed value.
+    ConstantCondition { line: usize, instruction: String },
+    /// A label named like a predefined symbol, such as `(SCREEN)`.
+    ShadowedPredefined { line: usize, label: String },
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::UselessAInstruction { line, symbol } => write!(
+                f,
+                "line {}: '@{}' is overwritten by the next A-instruction",
+                line, symbol
+            ),
+            Lint::ConstantCondition { line, instruction } => write!(
+                f,
+                "line {}: '{}' jumps on a constant, not a computed condition",
+                line, instruction
+            ),
+            // the assembler warns about the same label, in the same words
+            Lint::ShadowedPredefined { line, label } => AssemblerWarning::ShadowedPredefined {
+                line: *line,
+                label: label.clone(),
+            }
+            .fmt(f),
+        }
+    }
+}
+
+/// Looks for common Hack pitfalls in `source`, in source order. Lines that
+/// are no instruction at all are left to the assembler to report.
+pub fn lint(source: &str) -> Vec<Lint> {
+    let mut parser = Parser::from_source(source);
+    let predefined = SymbolTable::new();
+    let mut lints = Vec::new();
+    // the A-instruction just before, if nothing came between
+    let mut pending_a: Option<(usize, String)> = None;
+
+    while let Some(Ok(line)) = parser.advance() {
+        let line_number = parser.get_line_number();
+        match parser.instruction_type(&line) {
+            Some(InstructionType::AInstruction) => {
+                if let Some((line, symbol)) = pending_a.take() {
+                    lints.push(Lint::UselessAInstruction { line, symbol });
+                }
+                pending_a = Some((line_number, line[1..].to_string()));
+            }
+            Some(InstructionType::LInstruction) => {
+                // a jump may land between the two A-instructions
+                pending_a = None;
+                let Some(label) = parser.symbol(line) else {
+                    continue;
+                };
+                if predefined.get_kind(&label) == Some(SymbolKind::Predefined) {
+                    lints.push(Lint::ShadowedPredefined {
+                        line: line_number,
+                        label,
+                    });
+                }
+            }
+            Some(InstructionType::CInstruction) => {
+                pending_a = None;
+                let conditional = line
+                    .split_once(';')
+                    .is_some_and(|(_, jump)| !jump.eq_ignore_ascii_case("JMP"));
+                if conditional && ["0", "1", "-1"].contains(&comp_field(&line)) {
+                    lints.push(Lint::ConstantCondition {
+                        line: line_number,
+                        instruction: line,
+                    });
+                }
+            }
+            None => pending_a = None,
+        }
+    }
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_flag_an_a_instruction_overwritten_by_the_next_one() {
+        assert_eq!(
+            lint("@i\n@j\nM=0\n"),
+            [Lint::UselessAInstruction {
+                line: 1,
+                symbol: "i".to_string()
+            }]
+        );
+        // a label in between may be a jump target
+        assert_eq!(lint("@i\n(LOOP)\n@j\nM=0\n"), []);
+    }
+
+    #[test]
+    fn should_flag_a_conditional_jump_on_a_constant() {
+        assert_eq!(
+            lint("@END\n0;JEQ\n@END\n0;JMP\n@END\nD;JGT\n"),
+            [Lint::ConstantCondition {
+                line: 2,
+                instruction: "0;JEQ".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn should_flag_a_label_shadowing_a_predefined_symbol() {
+        let lints = lint("(SCREEN)\n@SCREEN\n0;JMP\n(LOOP)\n");
+        assert_eq!(
+            lints,
+            [Lint::ShadowedPredefined {
+                line: 1,
+                label: "SCREEN".to_string()
+            }]
+        );
+        assert_eq!(
+            lints[0].to_string(),
+            "line 1: label 'SCREEN' shadows a predefined symbol"
+        );
+        let warning = AssemblerWarning::ShadowedPredefined {
+            line: 1,
+            label: "SCREEN".to_string(),
+        };
+        assert_eq!(lints[0].to_string(), warning.to_string());
+    }
+
+    #[test]
+    fn should_leave_a_malformed_label_to_the_assembler() {
+        assert_eq!(lint("(\n(LOOP\n@0\n"), []);
+    }
+
+    #[test]
+    fn should_find_nothing_in_a_clean_program() {
+        let source = std::fs::read_to_string("asm-files/Sum1ToN.asm").unwrap();
+        assert_eq!(lint(&source), []);
+    }
+}
@@ -1,19 +1,513 @@
 use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::thread;
 
-use project05_assembler::hack_assembler::HackAssembler;
+use project05_assembler::{
+    error::AssemblerError,
+    hack_assembler::{diff_hack, parse_hack, write_atomically, HackAssembler},
+    json::json_string,
+    options::{AssemblerOptions, OutputFormat, RAM_LIMIT},
+    parser,
+};
+
+const USAGE: &str = "Usage: assembler <input.asm>... [-o <output.hack>] [--binary | --hex]
+                 [--extended-alu] [--annotate] [--listing <path>] [--dump-symbols <path>]
+                 [--sourcemap <path>] [--diff <reference.hack>] [--verify-dir <path>]
+                 [--dir <path> [--recursive]] [--check] [--stats [--json]]
+                 [--max-ram <address>] [--pad <size>]
+                 [--quiet | --verbose]
+Each input is assembled next to itself, with a .hack extension, unless -o is given.
+--dir adds every .asm file in a directory, and in its subdirectories with --recursive.
+-o, --listing, --dump-symbols, --sourcemap and --diff take a single input.
+Use - as the input or output path to read from stdin or write to stdout.
+An input file splices in the file named by each // include \"path.asm\" line,
+relative to itself; stdin has nothing to include from.
+--binary writes each instruction as two big-endian bytes instead of a text line,
+--hex as a line of four uppercase hex digits.
+--extended-alu accepts the shifts A<<, D<<, M<<, A>>, D>> and M>>.
+--annotate ends each text line with the instruction it came from, which strict
+emulators reject.
+--check reports every error in each input without writing anything.
+--stats prints the instruction, label and variable counts of each input,
+with --json as one JSON object per input on stdout, whatever the verbosity.
+--max-ram fails when a variable would take that address or one above it, 16384 by default
+and at most 32768.
+--pad fills the output with zero words up to <size> instructions.
+--sourcemap writes JSON mapping each ROM address to its source line.
+--diff reports each address where the program differs from a reference .hack file.
+--verify-dir compares each input with the .hack file of the same name in a directory.
+--quiet prints errors only; --verbose also reports single inputs.
+Errors show the line they point at, in color on a terminal unless NO_COLOR is set.";
+
+// How much goes to stderr besides errors, which are always printed
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+struct Args {
+    inputs: Vec<String>,
+    output: Option<String>,
+    format: OutputFormat,
+    extended_alu: bool,
+    annotate: bool,
+    listing: Option<String>,
+    dump_symbols: Option<String>,
+    sourcemap: Option<String>,
+    diff: Option<String>,
+    verify_dir: Option<String>,
+    dir: Option<String>,
+    recursive: bool,
+    check: bool,
+    stats: bool,
+    json: bool,
+    max_ram: usize,
+    pad: Option<usize>,
+    verbosity: Verbosity,
+}
 
 fn main() {
-    // $ HackAssembler Add.asm
-    let args: Vec<String> = env::args().collect();
-    // Check filename is provided
-    if args.len() < 2 {
-        eprintln!("No files provided!");
+    // $ HackAssembler Add.asm [Max.asm ...] [-o Add.hack]
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut args = match parse_args(&args) {
+        Ok(args) => args,
+        // with no arguments parsed, the verbosity is the default one
+        Err(message) => {
+            log(
+                Verbosity::Normal,
+                Verbosity::Quiet,
+                &format!("{}\n{}", message, USAGE),
+            );
+            process::exit(1);
+        }
+    };
+    if let Some(dir) = &args.dir {
+        let mut found = Vec::new();
+        if let Err(err) = asm_files(Path::new(dir), args.recursive, &mut found) {
+            log(
+                args.verbosity,
+                Verbosity::Quiet,
+                &format!("{}: {}", dir, err),
+            );
+            process::exit(1);
+        }
+        found.sort();
+        args.inputs
+            .extend(found.iter().map(|path| path.to_string_lossy().into_owned()));
+    }
+    // several inputs are reported by default, a single one with --verbose
+    let report = if args.inputs.len() > 1 || args.dir.is_some() {
+        Verbosity::Normal
+    } else {
+        Verbosity::Verbose
+    };
+
+    // keep going past a failed file, but still fail the whole run
+    let mut assembled = 0;
+    let mut failed = false;
+    for (input, (output, result)) in args.inputs.iter().zip(assemble_all(&args)) {
+        match result {
+            Ok(()) => {
+                assembled += 1;
+                if args.check {
+                    log(args.verbosity, report, &format!("{}: ok", input));
+                } else if let Some(reference) = &args.diff {
+                    log(
+                        args.verbosity,
+                        report,
+                        &format!("{}: matches {}", input, reference),
+                    );
+                } else if args.verify_dir.is_some() {
+                    log(args.verbosity, report, &format!("{}: pass", input));
+                } else {
+                    log(
+                        args.verbosity,
+                        report,
+                        &format!("{}: assembled into {}", input, output),
+                    );
+                }
+            }
+            Err(errors) => {
+                for err in errors {
+                    log(
+                        args.verbosity,
+                        Verbosity::Quiet,
+                        &format!("{}: {}", input, err),
+                    );
+                }
+                failed = true;
+            }
+        }
+    }
+    let verb = if args.check {
+        "checked"
+    } else if args.diff.is_some() {
+        "matched"
+    } else if args.verify_dir.is_some() {
+        "passed"
+    } else {
+        "assembled"
+    };
+    let summary = format!("{} {} of {} files", verb, assembled, args.inputs.len());
+    log(args.verbosity, report, &summary);
+    if failed {
         process::exit(1);
     }
-    // Filename
-    let filename = &args[1];
-    // Create a HackAssembler
-    let assembler = HackAssembler::new(filename);
-    // assembler.execute();
+}
+
+// Every diagnostic goes through here: printed to stderr unless it is less
+// important than `level` and `verbosity` asks for less
+fn log(verbosity: Verbosity, level: Verbosity, message: &str) {
+    if verbosity >= level {
+        eprintln!("{}", message);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut inputs = Vec::new();
+    let mut output = None;
+    let mut format = OutputFormat::Text;
+    let mut extended_alu = false;
+    let mut annotate = false;
+    let mut listing = None;
+    let mut dump_symbols = None;
+    let mut sourcemap = None;
+    let mut diff = None;
+    let mut verify_dir = None;
+    let mut dir = None;
+    let mut recursive = false;
+    let mut check = false;
+    let mut stats = false;
+    let mut json = false;
+    let mut max_ram = AssemblerOptions::default().max_ram;
+    let mut pad = None;
+    let mut verbosity = Verbosity::Normal;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(value_of(arg, args.next())?),
+            "--binary" => format = OutputFormat::Binary,
+            "--hex" => format = OutputFormat::Hex,
+            "--extended-alu" => extended_alu = true,
+            "--annotate" => annotate = true,
+            "--listing" => listing = Some(value_of(arg, args.next())?),
+            "--dump-symbols" => dump_symbols = Some(value_of(arg, args.next())?),
+            "--sourcemap" => sourcemap = Some(value_of(arg, args.next())?),
+            "--diff" => diff = Some(value_of(arg, args.next())?),
+            "--verify-dir" => verify_dir = Some(value_of(arg, args.next())?),
+            "--dir" => dir = Some(value_of(arg, args.next())?),
+            "--recursive" => recursive = true,
+            "--check" => check = true,
+            "--stats" => stats = true,
+            "--json" => json = true,
+            "--max-ram" => max_ram = number_of(arg, args.next())?,
+            "--pad" => pad = Some(number_of(arg, args.next())?),
+            "--quiet" => verbosity = Verbosity::Quiet,
+            "--verbose" => verbosity = Verbosity::Verbose,
+            _ if arg.starts_with("--") => return Err(format!("Unexpected argument '{}'", arg)),
+            _ => inputs.push(arg.clone()),
+        }
+    }
+
+    if json && output.as_deref() == Some("-") {
+        return Err("--json writes to stdout, so the output cannot be - too".to_string());
+    }
+    if max_ram > RAM_LIMIT {
+        return Err(AssemblerError::MaxRamTooLarge(max_ram).to_string());
+    }
+
+    // Check filename is provided
+    if inputs.is_empty() && dir.is_none() {
+        return Err("No files provided!".to_string());
+    }
+    if (inputs.len() > 1 || dir.is_some())
+        && (output.is_some()
+            || listing.is_some()
+            || dump_symbols.is_some()
+            || sourcemap.is_some()
+            || diff.is_some())
+    {
+        return Err(
+            "-o, --listing, --dump-symbols, --sourcemap and --diff take a single input".to_string(),
+        );
+    }
+    Ok(Args {
+        inputs,
+        output,
+        format,
+        extended_alu,
+        annotate,
+        listing,
+        dump_symbols,
+        sourcemap,
+        diff,
+        verify_dir,
+        dir,
+        recursive,
+        check,
+        stats,
+        json,
+        max_ram,
+        pad,
+        verbosity,
+    })
+}
+
+fn value_of(flag: &str, value: Option<&String>) -> Result<String, String> {
+    value
+        .cloned()
+        .ok_or_else(|| format!("Missing path after {}", flag))
+}
+
+fn number_of(flag: &str, value: Option<&String>) -> Result<usize, String> {
+    let value = value.ok_or_else(|| format!("Missing number after {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number '{}' after {}", value, flag))
+}
+
+// Assembles every input on a bounded pool of threads, each taking every
+// `workers`-th file, and returns the outputs and results in input order
+fn assemble_all(args: &Args) -> Vec<(String, Result<(), Vec<String>>)> {
+    let workers = thread::available_parallelism()
+        .map_or(1, |workers| workers.get())
+        .min(args.inputs.len())
+        .max(1);
+    let mut results: Vec<_> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|worker| {
+                scope.spawn(move || {
+                    args.inputs
+                        .iter()
+                        .enumerate()
+                        .skip(worker)
+                        .step_by(workers)
+                        .map(|(index, input)| {
+                            let output =
+                                args.output.clone().unwrap_or_else(|| default_output(input));
+                            let result = assemble(args, input, &output);
+                            (index, output, result)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|(index, _, _)| *index);
+    results
+        .into_iter()
+        .map(|(_, output, result)| (output, result))
+        .collect()
+}
+
+// Collects the .asm files in `dir`, descending into subdirectories if
+// `recursive`; anything else is skipped
+fn asm_files(dir: &Path, recursive: bool, found: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive {
+                asm_files(&path, recursive, found)?;
+            }
+        } else if path.extension().is_some_and(|extension| extension == "asm") {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+// By default the output sits next to the input, with a .hack extension
+fn default_output(input: &str) -> String {
+    if input == "-" {
+        return "-".to_string();
+    }
+    Path::new(input)
+        .with_extension("hack")
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Assembles one input, or with --check only validates it, returning every
+// error it found with the source line it points at; with --diff or
+// --verify-dir, a mismatch with the reference is an error too
+fn assemble(args: &Args, input: &str, output: &str) -> Result<(), Vec<String>> {
+    let source = read_source(input).map_err(|err| vec![err.to_string()])?;
+    let color = io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
+    let render = |errors: Vec<AssemblerError>| -> Vec<String> {
+        errors
+            .iter()
+            .map(|err| err.diagnostic(&source).with_color(color).to_string())
+            .collect()
+    };
+    if let Some(reference) = &args.diff {
+        let mismatches = compare(args, &source, reference).map_err(render)?;
+        return if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(mismatches)
+        };
+    }
+    if let Some(references) = &args.verify_dir {
+        let reference = reference_for(references, input);
+        let expected = fs::read_to_string(&reference)
+            .map_err(|err| vec![format!("{}: {}", reference.display(), err)])?;
+        let mut hack_assembler = HackAssembler::from_source_with_options(&source, options(args));
+        return match hack_assembler.verify_against(&expected) {
+            Ok(None) => Ok(()),
+            Ok(Some(line)) => Err(vec![format!(
+                "fail: differs from {} at line {}",
+                reference.display(),
+                line
+            )]),
+            Err(err) => Err(render(vec![err])),
+        };
+    }
+    assemble_source(args, &source, input, output).map_err(render)
+}
+
+// The reference of an input under --verify-dir: the .hack file of the same
+// name in the references directory
+fn reference_for(references: &str, input: &str) -> PathBuf {
+    let name = Path::new(input).with_extension("hack");
+    Path::new(references).join(name.file_name().unwrap_or_default())
+}
+
+// Assembles `source` and describes each way it differs from the reference
+fn compare(args: &Args, source: &str, reference: &str) -> Result<Vec<String>, Vec<AssemblerError>> {
+    let expected = fs::read_to_string(reference)
+        .map_err(AssemblerError::from)
+        .and_then(|text| parse_hack(&text))
+        .map_err(|err| vec![err])?;
+    let words =
+        HackAssembler::from_source_with_options(source, options(args)).assemble_collecting()?;
+    let mut mismatches: Vec<String> = diff_hack(&words, &expected)
+        .iter()
+        .map(|(address, word, expected)| {
+            format!(
+                "address {}: {:016b}, expected {:016b}",
+                address, word, expected
+            )
+        })
+        .collect();
+    if words.len() != expected.len() {
+        mismatches.push(format!(
+            "{} words, expected {}",
+            words.len(),
+            expected.len()
+        ));
+    }
+    Ok(mismatches)
+}
+
+fn assemble_source(
+    args: &Args,
+    source: &str,
+    input: &str,
+    output: &str,
+) -> Result<(), Vec<AssemblerError>> {
+    let mut hack_assembler = HackAssembler::from_source_with_options(source, options(args));
+    if args.check {
+        hack_assembler.check()?;
+        print_diagnostics(args, &hack_assembler, input);
+        return Ok(());
+    }
+    write_outputs(args, source, &mut hack_assembler, input, output).map_err(|err| vec![err])
+}
+
+fn options(args: &Args) -> AssemblerOptions {
+    AssemblerOptions {
+        format: args.format,
+        extended_alu: args.extended_alu,
+        annotate: args.annotate,
+        max_ram: args.max_ram,
+        pad: args.pad,
+        ..AssemblerOptions::default()
+    }
+}
+
+// The source of an input with its includes spliced in, or stdin as it is
+fn read_source(input: &str) -> Result<String, AssemblerError> {
+    if input != "-" {
+        return parser::read_source(input);
+    }
+    let mut source = String::new();
+    io::stdin().lock().read_to_string(&mut source)?;
+    Ok(source)
+}
+
+// Warnings, and the statistics with --stats, go to stderr after assembly;
+// statistics as JSON are for tools, so they go to stdout even with --quiet
+fn print_diagnostics(args: &Args, hack_assembler: &HackAssembler, input: &str) {
+    for warning in hack_assembler.warnings() {
+        log(
+            args.verbosity,
+            Verbosity::Normal,
+            &format!("{}: warning: {}", input, warning),
+        );
+    }
+    if args.stats && args.json {
+        let stats = hack_assembler.stats().to_json();
+        println!(
+            "{{\"input\": {}, \"stats\": {}}}",
+            json_string(input),
+            stats
+        );
+    } else if args.stats {
+        log(
+            args.verbosity,
+            Verbosity::Normal,
+            &format!("{}: {}", input, hack_assembler.stats()),
+        );
+    }
+}
+
+// The I/O error of writing `path`, with the path in its message, since the
+// CLI otherwise reports errors against the input
+fn naming(path: &str, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", path, err))
+}
+
+fn write_outputs(
+    args: &Args,
+    source: &str,
+    hack_assembler: &mut HackAssembler,
+    input: &str,
+    output: &str,
+) -> Result<(), AssemblerError> {
+    let mut words: Vec<u8> = Vec::new();
+    hack_assembler.write_output(&mut words)?;
+    print_diagnostics(args, hack_assembler, input);
+
+    if output == "-" {
+        let mut out = BufWriter::new(io::stdout().lock());
+        out.write_all(&words)?;
+        out.flush()?;
+    } else {
+        // a failed write never leaves a half-written program behind
+        write_atomically(output, &words).map_err(|err| naming(output, err))?;
+    }
+
+    if let Some(dump_symbols) = &args.dump_symbols {
+        hack_assembler.dump_symbols(File::create(dump_symbols)?)?;
+    }
+
+    if let Some(listing) = &args.listing {
+        HackAssembler::from_source_with_options(source, options(args))
+            .write_listing(File::create(listing)?)?;
+    }
+
+    if let Some(sourcemap) = &args.sourcemap {
+        HackAssembler::from_source_with_options(source, options(args))
+            .write_sourcemap(File::create(sourcemap)?)?;
+    }
+    Ok(())
 }
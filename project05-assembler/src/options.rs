@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+
+use crate::error::AssemblerError;
+
+/// One past the last RAM address an A-instruction can load, and so the
+/// largest `max_ram` there is.
+pub const RAM_LIMIT: usize = 32768;
+
+/// How the assembled program is written out.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OutputFormat {
+    /// One 16-character binary line per instruction, the `.hack` format.
+    Text,
+    /// Two big-endian bytes per instruction.
+    Binary,
+    /// One line of four uppercase hex digits per instruction.
+    Hex,
+}
+
+/// Settings for a [`HackAssembler`](crate::hack_assembler::HackAssembler).
+/// The default assembles like `HackAssembler::new`: text output into
+/// `hack-files/`, lenient parsing, mnemonics in either case and the standard
+/// ALU only.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AssemblerOptions {
+    pub format: OutputFormat,
+    pub output_dir: PathBuf,
+    /// Rejects a label named like a predefined symbol, and a line that is no
+    /// instruction, instead of warning about it or skipping it. Symbol names
+    /// follow the Hack lexical rules in either mode, after `@`, in labels
+    /// and in `#define`.
+    pub strict: bool,
+    /// Accepts `d=m+1;jgt` as well as `D=M+1;JGT`.
+    pub allow_lowercase: bool,
+    /// Accepts the shifts of the extended ALU, such as `D=D<<`.
+    pub extended_alu: bool,
+    /// The most instructions a program may have, 32768 for the Hack ROM.
+    pub rom_limit: usize,
+    /// Variables take RAM addresses from 16 up to, but not including, this
+    /// one: 16384 by default, where the screen starts, and at most
+    /// [`RAM_LIMIT`].
+    pub max_ram: usize,
+    /// Treats lines starting with `;` as comments; `0;JMP` is still a jump.
+    pub semicolon_comments: bool,
+    /// Follows each line of text output with `// ` and the instruction it
+    /// came from. Handy for teaching, but no longer plain `.hack`.
+    pub annotate: bool,
+    /// Fills the output with zero words up to this many instructions, for
+    /// emulators that load a fixed-size ROM image.
+    pub pad: Option<usize>,
+}
+
+impl Default for AssemblerOptions {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::Text,
+            output_dir: PathBuf::from("hack-files"),
+            strict: false,
+            allow_lowercase: true,
+            extended_alu: false,
+            rom_limit: 32768,
+            max_ram: 16384,
+            semicolon_comments: false,
+            annotate: false,
+            pad: None,
+        }
+    }
+}
+
+impl AssemblerOptions {
+    /// Checks for settings no program could assemble with, such as a
+    /// `max_ram` past [`RAM_LIMIT`]; every assembly runs this first.
+    pub fn validate(&self) -> Result<(), AssemblerError> {
+        if self.max_ram > RAM_LIMIT {
+            return Err(AssemblerError::MaxRamTooLarge(self.max_ram));
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,11 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader, Lines, Result},
+    fs::{self, File},
+    io::{BufRead, BufReader, Result},
+    path::{Path, PathBuf},
 };
 
+use crate::error::AssemblerError;
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum InstructionType {
     AInstruction,
@@ -10,207 +13,708 @@ pub enum InstructionType {
     LInstruction,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AValue {
+    Constant(u16),
+    Symbol(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Instruction {
+    A(AValue),
+    C {
+        dest: Option<String>,
+        comp: String,
+        jump: Option<String>,
+    },
+    Label(String),
+    Define {
+        name: String,
+        value: u16,
+    },
+}
+
+/// Where an instruction sits in the source: the byte range of its text,
+/// without indentation or comment, and its 1-based line.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+}
+
+/// Parses Hack source line by line. The whole source is read into memory
+/// up front, so both assembler passes walk the same buffer.
+///
+/// A file read with `new` or [`read_source`] may splice in other files with a
+/// `// include "path.asm"` line, resolved relative to the including file.
+/// Line numbers and spans then count the lines and bytes of the spliced
+/// buffer, as if its lines were joined by `\n`.
+///
+/// Besides `//` line comments, C-style `/* ... */` block comments may span
+/// any number of lines; they are blanked out while the source is buffered.
+///
+/// A label may share its line with the instruction it labels, as in
+/// `(LOOP) D=D+1`. `advance` then returns the label and the instruction as
+/// two lines, both with the line number of their source line, so such code
+/// assembles just like the label on a line of its own.
 pub struct Parser {
-    lines: Lines<BufReader<File>>,
+    // every line with the byte offset it starts at
+    lines: Vec<(usize, String)>,
+    cursor: usize,
+    // where the line at `cursor` resumes after a label was split off it
+    offset: usize,
     line_count: usize,
+    line_number: usize,
+    instruction_index: usize,
+    span: Span,
+    extended_alu: bool,
+    semicolon_comments: bool,
 }
 
 impl Parser {
-    pub fn new(filename: &str) -> Result<Self> {
-        let path = format!("asm-files/{}", &filename);
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
+    /// Opens `asm-files/{filename}` and buffers it, with its includes spliced
+    /// in; see [`Parser::from_reader`] for sources that are no file.
+    pub fn new(filename: &str) -> std::result::Result<Self, AssemblerError> {
+        let source = read_source(Path::new("asm-files").join(filename))?;
+        Ok(Self::from_source(&source))
+    }
+
+    /// Buffers everything left in `reader`, such as stdin, an in-memory
+    /// cursor or a socket.
+    pub fn from_reader<R: BufRead>(mut reader: R) -> Result<Self> {
+        let mut source = String::new();
+        reader.read_to_string(&mut source)?;
+        Ok(Self::from_source(&source))
+    }
 
-        Ok(Self {
-            lines: reader.lines(),
+    /// Buffers `source`, which may end its lines with `\n` or `\r\n`.
+    pub fn from_source(source: &str) -> Self {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        let mut in_block = false;
+        for line in source.split_inclusive('\n') {
+            // the last line keeps its content even without a newline
+            let content = line.strip_suffix('\n').unwrap_or(line);
+            // files written on Windows end their lines with \r\n
+            let content = content.strip_suffix('\r').unwrap_or(content);
+            lines.push((start, blank_block_comments(content, &mut in_block)));
+            start += line.len();
+        }
+        Self {
+            lines,
+            cursor: 0,
+            offset: 0,
             line_count: 0,
-        })
+            line_number: 0,
+            instruction_index: 0,
+            span: Span::default(),
+            extended_alu: false,
+            semicolon_comments: false,
+        }
+    }
+
+    /// Teaches `comp` the shift operations of the extended ALU, `A<<`, `D<<`,
+    /// `M<<`, `A>>`, `D>>` and `M>>`, which are errors otherwise.
+    pub fn set_extended_alu(&mut self, enabled: bool) {
+        self.extended_alu = enabled;
     }
 
+    /// Treats a line starting with `;` as a comment, as some other
+    /// assemblers do. A `;` after a comp, as in `0;JMP`, still starts a jump.
+    pub fn set_semicolon_comments(&mut self, enabled: bool) {
+        self.semicolon_comments = enabled;
+    }
+
+    /// How many lines `advance` has returned: every line with code left after
+    /// comments are stripped, labels and directives included. It is no ROM
+    /// address; see [`Parser::instruction_index`] for that.
     pub fn get_line_count(&self) -> Option<usize> {
         Some(self.line_count)
     }
 
+    /// How many A- and C-instructions `advance` has returned, which is the
+    /// ROM address of the next instruction. Right after a label is returned,
+    /// it is the address the label stands for.
+    pub fn instruction_index(&self) -> usize {
+        self.instruction_index
+    }
+
+    /// The 1-based source line of the last line returned by `advance`,
+    /// counting blank and comment lines, unlike `get_line_count`.
+    pub fn get_line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// The span of the last line returned by `advance`.
+    pub fn get_span(&self) -> Span {
+        self.span
+    }
+
     // pub fn has_more_lines(&self) -> bool {
     //     let lines: Vec<&str> = self.input.split("\n").collect();
     //     lines.len() > self.index
     // }
 
     pub fn advance(&mut self) -> Option<Result<String>> {
-        while let Some(line) = self.lines.next() {
-            match line {
-                Ok(content) => {
-                    let trimmed = content.trim();
-                    if !trimmed.is_empty() & !trimmed.starts_with("//") {
-                        self.line_count += 1;
-                        // if trimmed.starts_with("(") {
-                        //     self.line_count += 1;
-                        // }
-                        return Some(Ok(content.trim().to_string()));
+        let Some((index, code_start, code, rest)) = self.next_code() else {
+            self.cursor = self.lines.len();
+            self.offset = 0;
+            self.line_number = self.lines.len();
+            return None;
+        };
+        let code = code.to_string();
+        match rest {
+            Some(rest) => (self.cursor, self.offset) = (index, rest),
+            None => (self.cursor, self.offset) = (index + 1, 0),
+        }
+        self.line_number = index + 1;
+        self.line_count += 1;
+        if self.define(&code).is_none()
+            && matches!(
+                self.instruction_type(&code),
+                Some(InstructionType::AInstruction) | Some(InstructionType::CInstruction)
+            )
+        {
+            self.instruction_index += 1;
+        }
+        let start = self.lines[index].0 + code_start;
+        self.span = Span {
+            start,
+            end: start + code.len(),
+            line: self.line_number,
+        };
+        Some(Ok(code))
+    }
+
+    /// The line the next `advance` returns, without consuming it, or `None`
+    /// at the end of the source.
+    pub fn peek(&self) -> Option<&str> {
+        self.next_code().map(|(_, _, code, _)| code)
+    }
+
+    // The next code from the cursor on: the index of its line, where in that
+    // line it starts, the code itself and, when it is a label followed by an
+    // instruction, where the rest of the line resumes
+    fn next_code(&self) -> Option<(usize, usize, &str, Option<usize>)> {
+        let mut offset = self.offset;
+        for (index, (_, content)) in self.lines.iter().enumerate().skip(self.cursor) {
+            let text = &content[offset..];
+            if let Some(code) = self.code(text) {
+                let code_start = offset + text.len() - text.trim_start().len();
+                if let Some(end) = code.find(')').filter(|_| code.starts_with('(')) {
+                    if !code[end + 1..].trim().is_empty() {
+                        let label = &code[..=end];
+                        return Some((index, code_start, label, Some(code_start + end + 1)));
                     }
                 }
-                Err(e) => return Some(Err(e)),
+                return Some((index, code_start, code, None));
             }
+            offset = 0;
         }
         None
     }
 
-    pub fn reinitialize_lines(&mut self, filename: &str) -> Result<()> {
-        let path = format!("asm-files/{}", &filename);
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
+    // The code of a line once comments are stripped, or `None` if nothing is
+    // left of it
+    fn code<'a>(&self, content: &'a str) -> Option<&'a str> {
+        let trimmed = strip_comment(content);
+        if trimmed.is_empty() || (self.semicolon_comments && trimmed.starts_with(';')) {
+            return None;
+        }
+        Some(trimmed)
+    }
 
-        self.lines = reader.lines();
-        self.line_count = 0;
+    /// Iterates over the remaining lines as typed instructions, each with the
+    /// span of source it came from.
+    pub fn instructions(
+        &mut self,
+    ) -> impl Iterator<Item = std::result::Result<(Instruction, Span), AssemblerError>> + '_ {
+        std::iter::from_fn(move || match self.advance()? {
+            Ok(line) => Some(
+                self.instruction(line)
+                    .map(|instruction| (instruction, self.span))
+                    .map_err(|err| err.at_line(self.line_number)),
+            ),
+            Err(err) => Some(Err(err.into())),
+        })
+    }
+
+    fn instruction(&self, line: String) -> std::result::Result<Instruction, AssemblerError> {
+        if let Some(define) = self.define(&line) {
+            let (name, value) = define?;
+            return Ok(Instruction::Define { name, value });
+        }
+        match self.instruction_type(&line) {
+            Some(InstructionType::AInstruction) => {
+                let symbol = self.symbol(line).unwrap();
+                match parse_constant(&symbol) {
+                    Some(num) if (0..=32767).contains(&num) => {
+                        Ok(Instruction::A(AValue::Constant(num as u16)))
+                    }
+                    Some(num) => Err(AssemblerError::ConstantOutOfRange(num)),
+                    None => Ok(Instruction::A(AValue::Symbol(symbol))),
+                }
+            }
+            Some(InstructionType::LInstruction) => match self.symbol(line.clone()) {
+                Some(label) if is_symbol(&label) => Ok(Instruction::Label(label)),
+                Some(_) => Err(AssemblerError::InvalidSymbol(line)),
+                None => Err(AssemblerError::MalformedLine(line)),
+            },
+            Some(InstructionType::CInstruction) => {
+                self.require_comp(&line)?;
+                self.dest(&line)?;
+                self.jump(&line)?;
+                let (dest, _, jump) = Self::c_fields(&line).unwrap();
+                Ok(Instruction::C {
+                    dest: dest.map(str::to_string),
+                    comp: comp_field(&line).to_string(),
+                    jump: jump.map(str::to_string),
+                })
+            }
+            None => Err(AssemblerError::MalformedLine(line)),
+        }
+    }
 
-        Ok(())
+    /// Rewinds to the first buffered line, so the next `advance` starts the
+    /// source over without reading it again.
+    pub fn reset(&mut self) {
+        self.cursor = 0;
+        self.offset = 0;
+        self.line_count = 0;
+        self.line_number = 0;
+        self.instruction_index = 0;
+        self.span = Span::default();
     }
 
     
+    /// Classifies a line, or returns `None` when it looks like no instruction
+    /// at all: a C-instruction needs a `=`, a `;` or a known comp.
     pub fn instruction_type(&self, line: &str) -> Option<InstructionType> {
-        if line.starts_with("@") && is_not_uppercase(line) {
-            return Some(InstructionType::AInstruction);
+        if line.starts_with("@") {
+            Some(InstructionType::AInstruction)
         } else if line.starts_with("(") {
-            return Some(InstructionType::LInstruction);
+            Some(InstructionType::LInstruction)
+        } else if line.contains(['=', ';']) || self.comp_bits(line).is_some() {
+            Some(InstructionType::CInstruction)
         } else {
-            return Some(InstructionType::CInstruction);
+            None
         }
     }
     
+    /// The symbol of an A-instruction or a label, `i` for `@i` and `LOOP`
+    /// for `(LOOP)`. Returns `None` for any other line, and for a label that
+    /// is missing its `)`.
     pub fn symbol(&self, line: String) -> Option<String> {
         let instruction_type = self.instruction_type(&line);
         match instruction_type {
-            Some(InstructionType::AInstruction) => Some(line[1..].to_string()),
-            Some(InstructionType::LInstruction) => Some(line[1..line.len() - 1].to_string()),
+            Some(InstructionType::AInstruction) => line.strip_prefix('@').map(str::to_string),
+            Some(InstructionType::LInstruction) => line
+                .strip_prefix('(')
+                .and_then(|label| label.strip_suffix(')'))
+                .map(str::to_string),
             _ => None,
         }
     }
     
-    pub fn dest(&self, line: &str) -> Option<&str> {
-        let instruction_type = self.instruction_type(&line);
-        if let Some(InstructionType::CInstruction) = instruction_type {
-            // check if "="
-            if line.contains("=") {
-                let instruction: Vec<&str> = line.split("=").collect();
-                match instruction[0] {
-                    "M" => return Some("001"),
-                    "D" => return Some("010"),
-                    "DM" => return Some("011"),
-                    "A" => return Some("100"),
-                    "AM" => return Some("101"),
-                    "AD" => return Some("110"),
-                    "ADM" => return Some("111"),
-                    _ => return Some("000"),
+    /// Parses a `#define NAME value` directive, which binds `NAME` to a
+    /// constant for `@NAME` without taking a RAM address. Returns `None` for
+    /// any other line.
+    pub fn define(&self, line: &str) -> Option<std::result::Result<(String, u16), AssemblerError>> {
+        let rest = line.strip_prefix("#define")?;
+        let invalid = || AssemblerError::InvalidDirective(line.to_string());
+        let mut parts = rest.split_whitespace();
+        let define = match (parts.next(), parts.next(), parts.next()) {
+            (Some(name), Some(value), None) if rest.starts_with(char::is_whitespace) => {
+                match parse_constant(value) {
+                    Some(value) if (0..=32767).contains(&value) => {
+                        Ok((name.to_string(), value as u16))
+                    }
+                    Some(value) => Err(AssemblerError::ConstantOutOfRange(value)),
+                    None => Err(invalid()),
                 }
             }
-            return Some("000");
+            _ => Err(invalid()),
+        };
+        Some(define)
+    }
+
+    /// Splits a C-instruction into its dest, comp and jump mnemonics as they
+    /// are written, for tools that show the source rather than the bits.
+    /// Nothing is checked, so `X=D+1` gives `Some("X")`. Returns `None` for an
+    /// A-instruction, a label or a line with no comp.
+    pub fn c_fields(line: &str) -> Option<(Option<&str>, &str, Option<&str>)> {
+        if line.starts_with(['@', '(']) {
+            return None;
         }
-        None
+        let (without_jump, jump) = match line.split_once(';') {
+            Some((without_jump, jump)) => (without_jump, Some(jump)),
+            None => (line, None),
+        };
+        let (dest, comp) = match without_jump.split_once('=') {
+            Some((dest, comp)) => (Some(dest), comp),
+            None => (None, without_jump),
+        };
+        (!comp.is_empty()).then_some((dest, comp, jump))
     }
-    
-    pub fn comp(&self, line: &str) -> Option<&str> {
-        let instruction_type = self.instruction_type(&line);
+
+    /// Returns the `ddd` bits of a C-instruction, `000` when it has no dest.
+    ///
+    /// The dest may name `A`, `D` and `M` in any order, so `MD` and `DM` give
+    /// the same bits, but each at most once.
+    pub fn dest(&self, line: &str) -> std::result::Result<Option<&str>, AssemblerError> {
+        let instruction_type = self.instruction_type(line);
         if let Some(InstructionType::CInstruction) = instruction_type {
-            if line.contains("=") {
-                let instruction: Vec<&str> = line.split("=").collect();
-                match instruction[1] {
-                    "0" => return Some("0101010"),
-                    "1" => return Some("0111111"),
-                    "-1" => return Some("0111010"),
-                    "D" => return Some("0001100"),
-                    "A" => return Some("0110000"),
-                    "M" => return Some("1110000"),
-                    "!D" => return Some("0001101"),
-                    "!A" => return Some("0110001"),
-                    "!M" => return Some("1110001"),
-                    "-D" => return Some("0001111"),
-                    "-A" => return Some("0110011"),
-                    "-M" => return Some("1110011"),
-                    "D+1" => return Some("0011111"),
-                    "A+1" => return Some("0110111"),
-                    "M+1" => return Some("1110111"),
-                    "D-1" => return Some("0001110"),
-                    "A-1" => return Some("0110010"),
-                    "M-1" => return Some("1110010"),
-                    "D+A" => return Some("0000010"),
-                    "D+M" => return Some("1000010"),
-                    "D-A" => return Some("0010011"),
-                    "D-M" => return Some("1010011"),
-                    "A-D" => return Some("0000111"),
-                    "M-D" => return Some("1000111"),
-                    "D&A" => return Some("0000000"),
-                    "D&M" => return Some("1000000"),
-                    "D|A" => return Some("0010101"),
-                    "D|M" => return Some("1010101"),
-                    _ => return None,
+            // check if "="
+            if let Some((dest, _)) = line.split_once('=') {
+                let unknown_dest = || AssemblerError::UnknownDest(dest.to_string());
+                // the registers may come in any order, but each only once
+                let registers = dest.to_ascii_uppercase();
+                let mut canonical = String::new();
+                for register in "ADM".chars() {
+                    match registers.matches(register).count() {
+                        0 => {}
+                        1 => canonical.push(register),
+                        _ => return Err(unknown_dest()),
+                    }
                 }
-            }
-            if line.contains(";") {
-                let instruction: Vec<&str> = line.split("=").collect();
-                match instruction[0] {
-                    "0" => return Some("0101010"),
-                    "1" => return Some("0111111"),
-                    "-1" => return Some("0111010"),
-                    "D" => return Some("0001100"),
-                    "A" => return Some("0110000"),
-                    "M" => return Some("1110000"),
-                    "!D" => return Some("0001101"),
-                    "!A" => return Some("0110001"),
-                    "!M" => return Some("1110001"),
-                    "-D" => return Some("0001111"),
-                    "-A" => return Some("0110011"),
-                    "-M" => return Some("1110011"),
-                    "D+1" => return Some("0011111"),
-                    "A+1" => return Some("0110111"),
-                    "M+1" => return Some("1110111"),
-                    "D-1" => return Some("0001110"),
-                    "A-1" => return Some("0110010"),
-                    "M-1" => return Some("1110010"),
-                    "D+A" => return Some("0000010"),
-                    "D+M" => return Some("1000010"),
-                    "D-A" => return Some("0010011"),
-                    "D-M" => return Some("1010011"),
-                    "A-D" => return Some("0000111"),
-                    "M-D" => return Some("1000111"),
-                    "D&A" => return Some("0000000"),
-                    "D&M" => return Some("1000000"),
-                    "D|A" => return Some("0010101"),
-                    "D|M" => return Some("1010101"),
-                    _ => return None,
+                if canonical.len() != registers.len() {
+                    return Err(unknown_dest());
                 }
+                return bits_of(&DESTS, &canonical)
+                    .map(Some)
+                    .ok_or_else(unknown_dest);
             }
+            return Ok(Some("000"));
+        }
+        Ok(None)
+    }
+
+    /// Returns the `a cccccc` bits of a C-instruction, or `None` for an unknown comp.
+    ///
+    /// The accepted comps are `0`, `1`, `-1`, `D`, `A`, `!D`, `!A`, `-D`, `-A`,
+    /// `D+1`, `A+1`, `D-1`, `A-1`, `D+A`, `D-A`, `A-D`, `D&A`, `D|A`, and every
+    /// form using `A` again with `M` in its place (`M`, `!M`, `D+M`, `D|M`, ...).
+    /// Like dest and jump, comp is matched case-insensitively.
+    pub fn comp(&self, line: &str) -> Option<&str> {
+        let instruction_type = self.instruction_type(line);
+        if let Some(InstructionType::CInstruction) = instruction_type {
+            return self.comp_bits(comp_field(line));
         }
         None
     }
+
+    /// Returns the `a cccccc` bits of a C-instruction like `comp`, but as an
+    /// error when the comp is missing, as in `D=` or `;JMP`, or unknown.
+    pub fn require_comp(&self, line: &str) -> std::result::Result<&str, AssemblerError> {
+        let comp = comp_field(line);
+        if comp.trim().is_empty() {
+            return Err(AssemblerError::MissingComp(line.to_string()));
+        }
+        self.comp(line)
+            .ok_or_else(|| AssemblerError::UnknownComp(comp.to_string()))
+    }
+
+    /// The three leading bits of a C-instruction: `111`, or `101` for a shift
+    /// of the extended ALU.
+    pub fn c_prefix(&self, line: &str) -> &str {
+        if self.extended_alu && shift_bits(comp_field(line)).is_some() {
+            "101"
+        } else {
+            "111"
+        }
+    }
+
+    fn comp_bits(&self, comp: &str) -> Option<&'static str> {
+        match comp_bits(comp) {
+            Some(bits) => Some(bits),
+            None if self.extended_alu => shift_bits(comp),
+            None => None,
+        }
+    }
     
-    pub fn jump(&self, line: &str) -> Option<&str> {
-        let instruction_type = self.instruction_type(&line);
+    /// Returns the `jjj` bits of a C-instruction, `000` when it has no jump,
+    /// or an error for anything but `JGT`, `JEQ`, `JGE`, `JLT`, `JNE`, `JLE`
+    /// and `JMP`.
+    pub fn jump(&self, line: &str) -> std::result::Result<Option<&str>, AssemblerError> {
+        let instruction_type = self.instruction_type(line);
         if let Some(InstructionType::CInstruction) = instruction_type {
             // check if contains ";"
-            if line.contains(";") {
-                let instruction: Vec<&str> = line.split(";").collect();
-                match instruction[1] {
-                    "JGT" => return Some("001"),
-                    "JEQ" => return Some("010"),
-                    "JGE" => return Some("011"),
-                    "JLT" => return Some("100"),
-                    "JNE" => return Some("101"),
-                    "JLE" => return Some("110"),
-                    "JMP" => return Some("111"),
-                    _ => return Some("000"),
+            if let Some((_, jump)) = line.split_once(';') {
+                return match bits_of(&JUMPS, jump) {
+                    Some(bits) => Ok(Some(bits)),
+                    None => Err(AssemblerError::UnknownJump(jump.to_string())),
+                };
+            }
+            return Ok(Some("000"));
+        }
+        Ok(None)
+    }
+}
+
+// The comp part of "dest=comp;jump", where dest and jump are optional
+pub(crate) fn comp_field(line: &str) -> &str {
+    let without_jump = line.split(';').next().unwrap_or(line);
+    without_jump.split('=').next_back().unwrap_or(without_jump)
+}
+
+/// Reads the file at `path` with every `// include "path.asm"` line replaced
+/// by the lines of the file it names, resolved relative to the including
+/// file. A file that ends up including itself is an `IncludeCycle`.
+pub fn read_source<P: AsRef<Path>>(path: P) -> std::result::Result<String, AssemblerError> {
+    let lines = read_lines(path.as_ref(), &mut Vec::new())?;
+    Ok(lines.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+// The lines of `path` with every include spliced in; `including` holds the
+// files being read, to catch a file that ends up including itself
+fn read_lines(
+    path: &Path,
+    including: &mut Vec<PathBuf>,
+) -> std::result::Result<Vec<String>, AssemblerError> {
+    let reader = BufReader::new(File::open(path)?);
+    let canonical = fs::canonicalize(path)?;
+    if including.contains(&canonical) {
+        return Err(AssemblerError::IncludeCycle(path.display().to_string()));
+    }
+    including.push(canonical);
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut lines = Vec::new();
+    let mut in_block = false;
+    for line in reader.lines() {
+        let line = line?;
+        // an include inside a block comment is commented out
+        match include_path(&blank_block_comments(&line, &mut in_block)) {
+            Some(included) => lines.extend(read_lines(&dir.join(included), including)?),
+            None => lines.push(line),
+        }
+    }
+    including.pop();
+    Ok(lines)
+}
+
+// The line with every character inside a `/* ... */` comment turned into a
+// space, so offsets into it stay valid; `in_block` carries an open comment
+// over to the next line. A `/*` after `//` is part of the line comment.
+pub(crate) fn blank_block_comments(line: &str, in_block: &mut bool) -> String {
+    let mut blanked = String::with_capacity(line.len());
+    let mut rest = line;
+    while !rest.is_empty() {
+        if *in_block {
+            match rest.find("*/") {
+                Some(end) => {
+                    blanked.push_str(&" ".repeat(end + 2));
+                    rest = &rest[end + 2..];
+                    *in_block = false;
+                }
+                None => {
+                    blanked.push_str(&" ".repeat(rest.len()));
+                    rest = "";
+                }
+            }
+        } else {
+            match (rest.find("/*"), rest.find("//")) {
+                (Some(start), line_comment) if line_comment.is_none_or(|index| start < index) => {
+                    blanked.push_str(&rest[..start]);
+                    blanked.push_str("  ");
+                    rest = &rest[start + 2..];
+                    *in_block = true;
+                }
+                _ => {
+                    blanked.push_str(rest);
+                    rest = "";
                 }
             }
-            return Some("000")
         }
-        None
     }
+    blanked
+}
+
+// The path of a `// include "path.asm"` line
+fn include_path(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix("//")?
+        .trim_start()
+        .strip_prefix("include")?
+        .trim()
+        .strip_prefix('"')?
+        .strip_suffix('"')
+}
+
+// The mnemonics of the standard ALU with their `a cccccc` bits, the one table
+// both comp_bits and comp_mnemonic read
+const COMPS: [(&str, &str); 28] = [
+    ("0", "0101010"),
+    ("1", "0111111"),
+    ("-1", "0111010"),
+    ("D", "0001100"),
+    ("A", "0110000"),
+    ("M", "1110000"),
+    ("!D", "0001101"),
+    ("!A", "0110001"),
+    ("!M", "1110001"),
+    ("-D", "0001111"),
+    ("-A", "0110011"),
+    ("-M", "1110011"),
+    ("D+1", "0011111"),
+    ("A+1", "0110111"),
+    ("M+1", "1110111"),
+    ("D-1", "0001110"),
+    ("A-1", "0110010"),
+    ("M-1", "1110010"),
+    ("D+A", "0000010"),
+    ("D+M", "1000010"),
+    ("D-A", "0010011"),
+    ("D-M", "1010011"),
+    ("A-D", "0000111"),
+    ("M-D", "1000111"),
+    ("D&A", "0000000"),
+    ("D&M", "1000000"),
+    ("D|A", "0010101"),
+    ("D|M", "1010101"),
+];
+
+// Comps with their operands swapped, which compute the same as the spelling
+// in the spec that each maps to
+const COMMUTED_COMPS: [(&str, &str); 9] = [
+    ("1+D", "D+1"),
+    ("1+A", "A+1"),
+    ("1+M", "M+1"),
+    ("A+D", "D+A"),
+    ("M+D", "D+M"),
+    ("A&D", "D&A"),
+    ("M&D", "D&M"),
+    ("A|D", "D|A"),
+    ("M|D", "D|M"),
+];
+
+// The dest mnemonics, with the registers in `ADM` order, and their `ddd` bits
+const DESTS: [(&str, &str); 7] = [
+    ("M", "001"),
+    ("D", "010"),
+    ("DM", "011"),
+    ("A", "100"),
+    ("AM", "101"),
+    ("AD", "110"),
+    ("ADM", "111"),
+];
+
+// The jump mnemonics and their `jjj` bits
+const JUMPS: [(&str, &str); 7] = [
+    ("JGT", "001"),
+    ("JEQ", "010"),
+    ("JGE", "011"),
+    ("JLT", "100"),
+    ("JNE", "101"),
+    ("JLE", "110"),
+    ("JMP", "111"),
+];
+
+// The bits of `mnemonic` in `table`, in either case
+fn bits_of(table: &[(&'static str, &'static str)], mnemonic: &str) -> Option<&'static str> {
+    let mnemonic = mnemonic.to_ascii_uppercase();
+    table
+        .iter()
+        .find(|(name, _)| *name == mnemonic)
+        .map(|(_, bits)| *bits)
 }
 
-fn is_not_uppercase(s: &str) -> bool {
-    s.chars().any(|c| c.is_lowercase())
+// The mnemonic of `bits` in `table`
+fn mnemonic_of(table: &[(&'static str, &'static str)], bits: &str) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(_, code)| *code == bits)
+        .map(|(name, _)| *name)
 }
 
+/// The comp mnemonic of the `a cccccc` bits of a C-instruction, e.g. `D+1`
+/// for `0011111`, or `None` if the standard ALU has no such comp.
+pub fn comp_mnemonic(code: &str) -> Option<&'static str> {
+    mnemonic_of(&COMPS, code)
+}
+
+/// The dest mnemonic of the `ddd` bits, with its registers in `ADM` order, or
+/// `None` for `000`, which stores nowhere.
+pub fn dest_mnemonic(code: &str) -> Option<&'static str> {
+    mnemonic_of(&DESTS, code)
+}
+
+/// The jump mnemonic of the `jjj` bits, or `None` for `000`, which never jumps.
+pub fn jump_mnemonic(code: &str) -> Option<&'static str> {
+    mnemonic_of(&JUMPS, code)
+}
+
+/// The spelling in the spec of a comp written with its operands swapped,
+/// e.g. `D+A` for `A+D`, or `None` if `comp` is no such form. Only `+`, `&`
+/// and `|` commute, so `A-D` stays as it is.
+pub fn canonical_comp(comp: &str) -> Option<&'static str> {
+    bits_of(&COMMUTED_COMPS, comp)
+}
+
+// The `a cccccc` bits of a comp mnemonic, in either case and operand order
+fn comp_bits(comp: &str) -> Option<&'static str> {
+    bits_of(&COMPS, comp).or_else(|| bits_of(&COMPS, canonical_comp(comp)?))
+}
+
+// The `a cccccc` bits of an extended ALU shift, which goes with the 101 prefix.
+// As in the CPU emulator, `a` picks M over A, c1 shifts left rather than
+// right and c2 picks D over A or M, so `D=D<<` is 1010110000010000
+fn shift_bits(comp: &str) -> Option<&'static str> {
+    match comp.to_ascii_uppercase().as_str() {
+        "A<<" => Some("0100000"),
+        "D<<" => Some("0110000"),
+        "M<<" => Some("1100000"),
+        "A>>" => Some("0000000"),
+        "D>>" => Some("0010000"),
+        "M>>" => Some("1000000"),
+        _ => None,
+    }
+}
+
+/// Parses the constant of an A-instruction or `#define`: decimal, or hex
+/// and binary with a `0x` or `0b` prefix, as in `@0xFF` and `@0b1010`.
+/// Returns `None` for anything that is no number, including `+5`, since Hack
+/// has no signed literals. A decimal `-5` still reads as -5, so that it is
+/// reported out of range; the range is left to the caller.
+pub fn parse_constant(text: &str) -> Option<i32> {
+    let (digits, radix) =
+        if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            (hex, 16)
+        } else if let Some(binary) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+            (binary, 2)
+        } else {
+            (text, 10)
+        };
+    // from_str_radix would take a leading sign
+    let unsigned = match radix {
+        10 => digits.strip_prefix('-').unwrap_or(digits),
+        _ => digits,
+    };
+    if unsigned.is_empty() || !unsigned.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    i32::from_str_radix(digits, radix).ok()
+}
+
+/// Whether `symbol` follows the Hack lexical rules: letters, digits, `_`,
+/// `.`, `$` and `:`, not starting with a digit.
+pub fn is_symbol(symbol: &str) -> bool {
+    !symbol.is_empty()
+        && !symbol.starts_with(|c: char| c.is_ascii_digit())
+        && symbol
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.$:".contains(c))
+}
+
+/// Returns the instruction text of a line: everything before the first `//`,
+/// trimmed. Hack has no string literals, so any `//` starts a comment.
+pub fn strip_comment(line: &str) -> &str {
+    match line.find("//") {
+        Some(index) => line[..index].trim(),
+        None => line.trim(),
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -271,7 +775,7 @@ mod tests {
 
         if let Ok(text) = line {
             assert_eq!(text, "(LOOP)".to_string());
-            assert_eq!(parser.line_count, 6);
+            assert_eq!(parser.line_count, 5);
         }
     }
 
@@ -310,13 +814,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn symbol_should_return_none_for_a_label_without_its_closing_paren() {
+        let parser = Parser::from_source("");
+        assert_eq!(parser.symbol("(".to_string()), None);
+        assert_eq!(parser.symbol("(LOOP".to_string()), None);
+        assert_eq!(parser.symbol("()".to_string()), Some(String::new()));
+        assert_eq!(
+            parser.symbol("(LOOP)".to_string()),
+            Some("LOOP".to_string())
+        );
+    }
+
+    #[test]
+    fn instructions_should_reject_an_unclosed_or_empty_label() {
+        let mut parser = Parser::from_source("(\n()\n");
+        let messages: Vec<String> = parser
+            .instructions()
+            .map(|instruction| instruction.unwrap_err().to_string())
+            .collect();
+        assert_eq!(
+            messages,
+            ["line 1: malformed line '('", "line 2: invalid symbol '()'"]
+        );
+    }
+
     #[test]
     fn call_dest_should_return_none_if_line_is_an_a_instruction() {
         let mut parser = Parser::new("Add.asm").unwrap();
         let line = parser.advance().unwrap();
 
         if let Ok(text) = line {
-            assert_eq!(parser.dest(&text), None)
+            assert_eq!(parser.dest(&text).unwrap(), None)
         }
     }
 
@@ -327,7 +856,7 @@ mod tests {
         let line = parser.advance().unwrap();
 
         if let Ok(text) = line {
-            assert_eq!(parser.dest(&text), Some("010")) // "D"
+            assert_eq!(parser.dest(&text).unwrap(), Some("010")) // "D"
         }
     }
 
@@ -342,13 +871,13 @@ mod tests {
     }
 
     #[test]
-    fn call_comp_should_return_110000_if_line_is_an_c_instruction() {
+    fn call_comp_should_return_0110000_if_line_is_an_c_instruction() {
         let mut parser = Parser::new("Add.asm").unwrap();
         parser.advance();
         let line = parser.advance().unwrap();
 
         if let Ok(text) = line {
-            assert_eq!(parser.comp(&text), Some("110000")) // "A"
+            assert_eq!(parser.comp(&text), Some("0110000")) // "A"
         }
     }
 
@@ -358,7 +887,7 @@ mod tests {
         let line = parser.advance().unwrap();
 
         if let Ok(text) = line {
-            assert_eq!(parser.jump(&text), None)
+            assert_eq!(parser.jump(&text).unwrap(), None)
         }
     }
 
@@ -378,7 +907,664 @@ mod tests {
         let line = parser.advance().unwrap();
 
         if let Ok(text) = line {
-            assert_eq!(parser.jump(&text), Some("001")) // "JGT"
+            assert_eq!(parser.jump(&text).unwrap(), Some("001")) // "JGT"
         }
     }
+
+    #[test]
+    fn dest_should_accept_the_registers_in_any_order() {
+        let parser = Parser::from_source("");
+        assert_eq!(parser.dest("D+1").unwrap(), Some("000"));
+        assert_eq!(parser.dest("M=D").unwrap(), Some("001"));
+        assert_eq!(parser.dest("DM=D").unwrap(), Some("011"));
+        assert_eq!(parser.dest("MD=D").unwrap(), Some("011"));
+        assert_eq!(parser.dest("AMD=D").unwrap(), Some("111"));
+        assert_eq!(parser.dest("DAM=D").unwrap(), Some("111"));
+    }
+
+    #[test]
+    fn dest_should_reject_unknown_or_repeated_registers() {
+        let parser = Parser::from_source("");
+        assert!(matches!(
+            parser.dest("AX=D"),
+            Err(AssemblerError::UnknownDest(dest)) if dest == "AX"
+        ));
+        assert!(matches!(
+            parser.dest("MM=D"),
+            Err(AssemblerError::UnknownDest(_))
+        ));
+        assert!(matches!(
+            parser.dest("=D"),
+            Err(AssemblerError::UnknownDest(_))
+        ));
+    }
+
+    #[test]
+    fn comp_dest_and_jump_should_ignore_the_case_of_mnemonics() {
+        let parser = Parser::from_source("");
+        assert_eq!(parser.comp("d=m+1;jgt"), parser.comp("D=M+1;JGT"));
+        assert_eq!(parser.dest("am=d").unwrap(), Some("101"));
+        assert_eq!(parser.jump("0;jmp").unwrap(), Some("111"));
+    }
+
+    #[test]
+    fn dest_and_jump_should_return_zero_codes_for_a_bare_comp() {
+        let parser = Parser::from_source("");
+        assert_eq!(parser.dest("D+1").unwrap(), Some("000"));
+        assert_eq!(parser.jump("D+1").unwrap(), Some("000"));
+        assert_eq!(parser.comp("D+1"), Some("0011111"));
+    }
+
+    #[test]
+    fn jump_should_map_every_mnemonic_to_its_bits() {
+        let parser = Parser::from_source("");
+        assert_eq!(parser.jump("D").unwrap(), Some("000"));
+        assert_eq!(parser.jump("D;JGT").unwrap(), Some("001"));
+        assert_eq!(parser.jump("D;JEQ").unwrap(), Some("010"));
+        assert_eq!(parser.jump("D;JGE").unwrap(), Some("011"));
+        assert_eq!(parser.jump("D;JLT").unwrap(), Some("100"));
+        assert_eq!(parser.jump("D;JNE").unwrap(), Some("101"));
+        assert_eq!(parser.jump("D;JLE").unwrap(), Some("110"));
+        assert_eq!(parser.jump("0;JMP").unwrap(), Some("111"));
+    }
+
+    #[test]
+    fn jump_should_reject_an_unknown_mnemonic() {
+        let parser = Parser::from_source("");
+        assert!(matches!(
+            parser.jump("D;JXX"),
+            Err(AssemblerError::UnknownJump(jump)) if jump == "JXX"
+        ));
+    }
+
+    #[test]
+    fn strip_comment_should_remove_trailing_and_full_line_comments() {
+        assert_eq!(strip_comment("// full line comment"), "");
+        assert_eq!(strip_comment("@5 // load constant"), "@5");
+        assert_eq!(strip_comment("D=M+1// increment"), "D=M+1");
+        assert_eq!(strip_comment("   \t// only a comment"), "");
+        assert_eq!(strip_comment("0;JMP"), "0;JMP");
+    }
+
+    #[test]
+    fn strip_comment_should_cut_at_the_first_double_slash() {
+        assert_eq!(strip_comment("@R1//first // second"), "@R1");
+        assert_eq!(strip_comment("////"), "");
+        assert_eq!(strip_comment("  D=A  /// doc-style comment"), "D=A");
+        assert_eq!(strip_comment("@a/b"), "@a/b");
+    }
+
+    #[test]
+    fn advance_should_skip_comment_lines_and_strip_trailing_comments() {
+        let mut parser = Parser::new("Comments.asm").unwrap();
+        let line = parser.advance().unwrap().unwrap();
+        assert_eq!(line, "@5");
+        assert_eq!(
+            parser.instruction_type(&line),
+            Some(InstructionType::AInstruction)
+        );
+        assert_eq!(parser.symbol(line), Some("5".to_string()));
+
+        let line = parser.advance().unwrap().unwrap();
+        assert_eq!(line, "D=A");
+        assert_eq!(parser.dest(&line).unwrap(), Some("010"));
+        assert_eq!(parser.comp(&line), Some("0110000"));
+        assert_eq!(parser.line_count, 2);
+        assert!(parser.advance().is_none());
+    }
+
+    #[test]
+    fn advance_should_trim_spaces_and_tabs_around_instructions() {
+        let mut parser = Parser::new("Indented.asm").unwrap();
+
+        let line = parser.advance().unwrap().unwrap();
+        assert_eq!(line, "@5");
+        assert_eq!(
+            parser.instruction_type(&line),
+            Some(InstructionType::AInstruction)
+        );
+
+        let line = parser.advance().unwrap().unwrap();
+        assert_eq!(line, "D=M");
+        assert_eq!(
+            parser.instruction_type(&line),
+            Some(InstructionType::CInstruction)
+        );
+
+        let line = parser.advance().unwrap().unwrap();
+        assert_eq!(line, "(LOOP)");
+        assert_eq!(
+            parser.instruction_type(&line),
+            Some(InstructionType::LInstruction)
+        );
+        assert_eq!(parser.symbol(line), Some("LOOP".to_string()));
+    }
+
+    #[test]
+    fn comp_should_be_read_from_every_c_instruction_form() {
+        let parser = Parser::new("Add.asm").unwrap();
+        assert_eq!(parser.comp("D=D+A"), Some("0000010"));
+        assert_eq!(parser.comp("D;JGT"), Some("0001100"));
+        assert_eq!(parser.comp("AM=M-1;JNE"), Some("1110010"));
+        assert_eq!(parser.comp("0;JMP"), Some("0101010"));
+        assert_eq!(parser.comp("D=X+1"), None);
+    }
+
+    #[test]
+    fn instructions_should_yield_typed_instructions_in_order() {
+        let mut parser = Parser::from_source("@2\n(LOOP)\n@i\nMD=M+1;JGT\n0;JMP\nD=A\n");
+        let instructions: Vec<Instruction> = parser.instructions().map(|i| i.unwrap().0).collect();
+        assert_eq!(
+            instructions,
+            vec![
+                Instruction::A(AValue::Constant(2)),
+                Instruction::Label("LOOP".to_string()),
+                Instruction::A(AValue::Symbol("i".to_string())),
+                Instruction::C {
+                    dest: Some("MD".to_string()),
+                    comp: "M+1".to_string(),
+                    jump: Some("JGT".to_string()),
+                },
+                Instruction::C {
+                    dest: None,
+                    comp: "0".to_string(),
+                    jump: Some("JMP".to_string()),
+                },
+                Instruction::C {
+                    dest: Some("D".to_string()),
+                    comp: "A".to_string(),
+                    jump: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn instructions_should_yield_errors_for_invalid_lines() {
+        let mut parser = Parser::from_source("@40000\nD=X+1\n");
+        let mut instructions = parser.instructions();
+        assert!(matches!(
+            instructions.next(),
+            Some(Err(AssemblerError::AtLine { line: 1, error }))
+                if matches!(*error, AssemblerError::ConstantOutOfRange(40000))
+        ));
+        assert!(matches!(
+            instructions.next(),
+            Some(Err(AssemblerError::AtLine { line: 2, error }))
+                if matches!(*error, AssemblerError::UnknownComp(_))
+        ));
+        assert!(instructions.next().is_none());
+    }
+
+    #[test]
+    fn get_line_number_should_count_blank_and_comment_lines() {
+        let mut parser = Parser::new("Add.asm").unwrap();
+        parser.advance();
+        assert_eq!(parser.get_line_number(), 7);
+        parser.advance();
+        assert_eq!(parser.get_line_number(), 8);
+        assert_eq!(parser.get_line_count(), Some(2));
+    }
+
+    #[test]
+    fn reset_should_start_the_buffered_lines_over() {
+        let mut parser = Parser::new("Add.asm").unwrap();
+        let first_pass: Vec<String> = std::iter::from_fn(|| parser.advance())
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(parser.get_line_count(), Some(6));
+
+        parser.reset();
+        assert_eq!(parser.get_line_count(), Some(0));
+        let second_pass: Vec<String> = std::iter::from_fn(|| parser.advance())
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(second_pass, first_pass);
+        assert_eq!(parser.get_line_number(), 12);
+    }
+
+    #[test]
+    fn instruction_type_should_return_none_for_a_line_that_is_no_instruction() {
+        let parser = Parser::from_source("");
+        assert_eq!(parser.instruction_type("gibberish"), None);
+        assert_eq!(
+            parser.instruction_type("D+1"),
+            Some(InstructionType::CInstruction)
+        );
+        assert_eq!(
+            parser.instruction_type("D=X"),
+            Some(InstructionType::CInstruction)
+        );
+        assert_eq!(
+            parser.instruction_type("X;JMP"),
+            Some(InstructionType::CInstruction)
+        );
+    }
+
+    #[test]
+    fn comp_should_reject_shifts_without_the_extended_alu() {
+        let parser = Parser::from_source("");
+        assert_eq!(parser.comp("D=D<<"), None);
+        assert_eq!(parser.c_prefix("D=D<<"), "111");
+    }
+
+    #[test]
+    fn comp_should_encode_shifts_with_the_extended_alu() {
+        let mut parser = Parser::from_source("");
+        parser.set_extended_alu(true);
+        assert_eq!(parser.comp("D=D<<"), Some("0110000"));
+        assert_eq!(parser.comp("M=M>>"), Some("1000000"));
+        assert_eq!(parser.c_prefix("D=D<<"), "101");
+        assert_eq!(parser.c_prefix("D=D+1"), "111");
+        assert_eq!(
+            parser.instruction_type("A<<"),
+            Some(InstructionType::CInstruction)
+        );
+    }
+
+    #[test]
+    fn comp_should_encode_shifts_like_the_cpu_emulator() {
+        let mut parser = Parser::from_source("");
+        parser.set_extended_alu(true);
+        let words = ["A<<", "D<<", "M<<", "A>>", "D>>", "M>>"]
+            .map(|comp| format!("{}{}", parser.c_prefix(comp), parser.comp(comp).unwrap()));
+        assert_eq!(
+            words,
+            [
+                "1010100000",
+                "1010110000",
+                "1011100000",
+                "1010000000",
+                "1010010000",
+                "1011000000"
+            ]
+        );
+    }
+
+    #[test]
+    fn define_should_parse_a_name_and_a_constant() {
+        let parser = Parser::from_source("");
+        assert_eq!(
+            parser.define("#define WIDTH 512").unwrap().unwrap(),
+            ("WIDTH".to_string(), 512)
+        );
+        assert!(parser.define("@WIDTH").is_none());
+    }
+
+    #[test]
+    fn define_should_reject_a_malformed_directive() {
+        let parser = Parser::from_source("");
+        for line in [
+            "#define WIDTH",
+            "#define WIDTH wide",
+            "#define A 1 2",
+            "#defineX 1",
+        ] {
+            assert!(matches!(
+                parser.define(line),
+                Some(Err(AssemblerError::InvalidDirective(_)))
+            ));
+        }
+        assert!(matches!(
+            parser.define("#define BIG 40000"),
+            Some(Err(AssemblerError::ConstantOutOfRange(40000)))
+        ));
+    }
+
+    #[test]
+    fn new_should_splice_in_an_included_file() {
+        let mut parser = Parser::new("include/Simple.asm").unwrap();
+        let lines: Vec<String> = std::iter::from_fn(|| parser.advance())
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(lines, ["@256", "D=A", "@SP", "M=D", "@2", "D=A"]);
+    }
+
+    #[test]
+    fn new_should_splice_in_nested_includes() {
+        let mut parser = Parser::new("include/Nested.asm").unwrap();
+        let lines: Vec<String> = std::iter::from_fn(|| parser.advance())
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            ["@256", "D=A", "@SP", "M=D", "@LCL", "M=0", "(END)", "@END", "0;JMP"]
+        );
+    }
+
+    #[test]
+    fn new_should_fail_on_a_file_including_itself() {
+        assert!(matches!(
+            Parser::new("include/SelfInclude.asm"),
+            Err(AssemblerError::IncludeCycle(path)) if path.ends_with("SelfInclude.asm")
+        ));
+    }
+
+    #[test]
+    fn include_path_should_only_read_include_comments() {
+        assert_eq!(include_path("// include \"Init.asm\""), Some("Init.asm"));
+        assert_eq!(
+            include_path("  //include \"lib/a.asm\"  "),
+            Some("lib/a.asm")
+        );
+        assert_eq!(include_path("// includes the init code"), None);
+        assert_eq!(include_path("@include"), None);
+    }
+
+    #[test]
+    fn mnemonics_should_round_trip_through_their_bits() {
+        for (table, mnemonic_of) in [
+            (
+                &COMPS[..],
+                comp_mnemonic as fn(&str) -> Option<&'static str>,
+            ),
+            (&DESTS[..], dest_mnemonic),
+            (&JUMPS[..], jump_mnemonic),
+        ] {
+            for (mnemonic, bits) in table {
+                assert_eq!(bits_of(table, mnemonic), Some(*bits));
+                assert_eq!(mnemonic_of(bits), Some(*mnemonic));
+            }
+        }
+        assert_eq!(dest_mnemonic("000"), None);
+        assert_eq!(jump_mnemonic("000"), None);
+        assert_eq!(comp_mnemonic("1111111"), None);
+    }
+
+    #[test]
+    fn is_symbol_should_follow_the_hack_lexical_rules() {
+        for symbol in ["loop.end", "LOOP", "_x", "sys$init", "a:b", "R15"] {
+            assert!(is_symbol(symbol), "{}", symbol);
+        }
+        for symbol in ["9abc", "a b", "", "a-b", "x+1"] {
+            assert!(!is_symbol(symbol), "{}", symbol);
+        }
+    }
+
+    #[test]
+    fn comp_should_encode_commuted_operands_like_the_spec_spelling() {
+        let parser = Parser::from_source("");
+        for (commuted, canonical) in COMMUTED_COMPS {
+            assert_eq!(canonical_comp(commuted), Some(canonical));
+            assert_eq!(
+                parser.comp(&format!("D={}", commuted)),
+                parser.comp(&format!("D={}", canonical)),
+                "{}",
+                commuted
+            );
+        }
+        assert_eq!(parser.comp("D=A+D"), Some("0000010"));
+        assert_eq!(parser.comp("D=m&d"), Some("1000000"));
+        assert_eq!(canonical_comp("D+A"), None);
+        assert_eq!(canonical_comp("D-A"), None);
+        assert_eq!(parser.comp("D=A-D"), Some("0000111"));
+    }
+
+    #[test]
+    fn require_comp_should_reject_a_c_instruction_without_a_comp() {
+        let parser = Parser::from_source("");
+        for line in ["D=", ";JMP", "M= ;JGT"] {
+            assert!(matches!(
+                parser.require_comp(line),
+                Err(AssemblerError::MissingComp(l)) if l == line
+            ));
+        }
+        assert_eq!(parser.require_comp("0;JMP").unwrap(), "0101010");
+        assert!(matches!(
+            parser.require_comp("D=X"),
+            Err(AssemblerError::UnknownComp(comp)) if comp == "X"
+        ));
+    }
+
+    #[test]
+    fn instructions_should_carry_the_span_of_their_source() {
+        let source = "// adds two\n@2\n  D=A // keep it\n";
+        let mut parser = Parser::from_source(source);
+        let spans: Vec<Span> = parser.instructions().map(|i| i.unwrap().1).collect();
+        assert_eq!(
+            spans[1],
+            Span {
+                start: 17,
+                end: 20,
+                line: 3
+            }
+        );
+        assert_eq!(&source[spans[1].start..spans[1].end], "D=A");
+        assert_eq!(&source[spans[0].start..spans[0].end], "@2");
+    }
+
+    #[test]
+    fn instructions_should_count_crlf_line_endings_in_spans() {
+        let source = "@2\r\nD=A\r\n";
+        let mut parser = Parser::from_source(source);
+        let spans: Vec<Span> = parser.instructions().map(|i| i.unwrap().1).collect();
+        assert_eq!(&source[spans[1].start..spans[1].end], "D=A");
+    }
+
+    #[test]
+    fn advance_should_strip_crlf_line_endings() {
+        let mut parser = Parser::from_source("(LOOP)\r\n@LOOP\r\n0;JMP\r\n");
+        let lines: Vec<String> = std::iter::from_fn(|| parser.advance())
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(lines, ["(LOOP)", "@LOOP", "0;JMP"]);
+    }
+
+    #[test]
+    fn advance_should_yield_a_last_line_without_a_newline() {
+        let mut parser = Parser::new("NoTrailingNewline.asm").unwrap();
+        let lines: Vec<String> = std::iter::from_fn(|| parser.advance())
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(lines.last().map(String::as_str), Some("M=D"));
+        assert_eq!(lines.len(), 6);
+
+        let mut parser = Parser::from_source("@2\nD=A");
+        parser.advance();
+        assert_eq!(parser.advance().unwrap().unwrap(), "D=A");
+        assert!(parser.advance().is_none());
+    }
+
+    #[test]
+    fn parse_constant_should_read_decimal_hex_and_binary() {
+        assert_eq!(parse_constant("42"), Some(42));
+        assert_eq!(parse_constant("0xFF"), Some(255));
+        assert_eq!(parse_constant("0Xff"), Some(255));
+        assert_eq!(parse_constant("0b100000000"), Some(256));
+        assert_eq!(parse_constant("0xFFFF"), Some(65535));
+        assert_eq!(parse_constant("0x"), None);
+        assert_eq!(parse_constant("0b102"), None);
+        assert_eq!(parse_constant("LOOP"), None);
+    }
+
+    #[test]
+    fn parse_constant_should_reject_a_sign() {
+        assert_eq!(parse_constant("+5"), None);
+        assert_eq!(parse_constant("-5"), Some(-5));
+        assert_eq!(parse_constant("--5"), None);
+        assert_eq!(parse_constant("0x+F"), None);
+        assert_eq!(parse_constant("0b-1"), None);
+    }
+
+    #[test]
+    fn comp_should_differ_only_in_the_a_bit_between_a_and_m_forms() {
+        let parser = Parser::from_source("");
+        let a_comps = [
+            "A", "!A", "-A", "A+1", "A-1", "D+A", "D-A", "A-D", "D&A", "D|A",
+        ];
+        for a_comp in a_comps {
+            let m_comp = a_comp.replace('A', "M");
+            let a_bits = parser.comp(&format!("D={}", a_comp)).unwrap();
+            let m_bits = parser.comp(&format!("D={}", m_comp)).unwrap();
+            assert_eq!(&a_bits[..1], "0", "{}", a_comp);
+            assert_eq!(&m_bits[..1], "1", "{}", m_comp);
+            assert_eq!(a_bits[1..], m_bits[1..], "{} and {}", a_comp, m_comp);
+        }
+        // those are all the M forms, and the comps using neither register
+        // keep the a bit clear
+        let m_forms = COMPS.iter().filter(|(comp, _)| comp.contains('M')).count();
+        assert_eq!(m_forms, a_comps.len());
+        for (comp, bits) in COMPS {
+            if !comp.contains(['A', 'M']) {
+                assert!(bits.starts_with('0'), "{}", comp);
+            }
+        }
+    }
+
+    #[test]
+    fn from_reader_should_buffer_an_in_memory_source() {
+        let mut parser = Parser::from_reader(std::io::Cursor::new("// two\n@2\nD=A\n")).unwrap();
+        assert_eq!(parser.advance().unwrap().unwrap(), "@2");
+        assert_eq!(parser.get_line_number(), 2);
+        assert_eq!(parser.advance().unwrap().unwrap(), "D=A");
+        assert!(parser.advance().is_none());
+    }
+
+    fn advanced_lines(parser: &mut Parser) -> Vec<String> {
+        std::iter::from_fn(|| parser.advance())
+            .map(|line| line.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn advance_should_skip_a_single_line_block_comment() {
+        let mut parser = Parser::from_source("/* sets D to 2 */\n@2\nD=A\n");
+        assert_eq!(advanced_lines(&mut parser), ["@2", "D=A"]);
+        assert_eq!(parser.get_line_number(), 3);
+    }
+
+    #[test]
+    fn advance_should_skip_a_block_comment_over_several_lines() {
+        let mut parser = Parser::from_source("@2\n/* first\n@3\n   last */\nD=A\n");
+        assert_eq!(advanced_lines(&mut parser), ["@2", "D=A"]);
+        assert_eq!(parser.get_line_number(), 5);
+    }
+
+    #[test]
+    fn advance_should_keep_an_instruction_next_to_a_block_comment() {
+        let source = "/* load */@2\nD=A/* copy */\nM=D // not /* a block\n@0\n";
+        let mut parser = Parser::from_source(source);
+        assert_eq!(advanced_lines(&mut parser), ["@2", "D=A", "M=D", "@0"]);
+
+        let mut parser = Parser::from_source(source);
+        let span = parser.instructions().next().unwrap().unwrap().1;
+        assert_eq!(&source[span.start..span.end], "@2");
+    }
+
+    #[test]
+    fn advance_should_skip_semicolon_comments_only_when_enabled() {
+        let source = "; note\n@END\n  ;; another note\n0;JMP\n";
+        let mut parser = Parser::from_source(source);
+        parser.set_semicolon_comments(true);
+        assert_eq!(advanced_lines(&mut parser), ["@END", "0;JMP"]);
+
+        let mut parser = Parser::from_source(source);
+        assert_eq!(advanced_lines(&mut parser)[0], "; note");
+    }
+
+    #[test]
+    fn counters_should_tell_source_lines_returned_lines_and_rom_addresses_apart() {
+        let source = "// adds 2\n@2\n\n(LOOP)\nD=A\n#define N 3\n@LOOP\n0;JMP\n";
+        let mut parser = Parser::from_source(source);
+        let mut counters = Vec::new();
+        while let Some(Ok(line)) = parser.advance() {
+            counters.push((
+                line,
+                parser.get_line_number(),
+                parser.get_line_count().unwrap(),
+                parser.instruction_index(),
+            ));
+        }
+        let counters: Vec<_> = counters
+            .iter()
+            .map(|(line, number, count, index)| (line.as_str(), *number, *count, *index))
+            .collect();
+        assert_eq!(
+            counters,
+            [
+                ("@2", 2, 1, 1),
+                ("(LOOP)", 4, 2, 1),
+                ("D=A", 5, 3, 2),
+                ("#define N 3", 6, 4, 2),
+                ("@LOOP", 7, 5, 3),
+                ("0;JMP", 8, 6, 4),
+            ]
+        );
+
+        parser.reset();
+        assert_eq!(parser.instruction_index(), 0);
+        assert_eq!(parser.get_line_count(), Some(0));
+    }
+
+    #[test]
+    fn peek_should_return_the_next_line_without_consuming_it() {
+        let mut parser = Parser::from_source("// two loads\n@2\n\n  @3 // again\n");
+        assert_eq!(parser.peek(), Some("@2"));
+        assert_eq!(parser.peek(), Some("@2"));
+        assert_eq!(parser.get_line_number(), 0);
+        assert_eq!(parser.advance().unwrap().unwrap(), "@2");
+
+        assert_eq!(parser.peek(), Some("@3"));
+        assert_eq!(parser.get_line_number(), 2);
+        assert_eq!(parser.advance().unwrap().unwrap(), "@3");
+        assert_eq!(parser.peek(), None);
+        assert!(parser.advance().is_none());
+    }
+
+    #[test]
+    fn advance_should_split_a_label_from_the_instruction_on_its_line() {
+        let mut parser = Parser::from_source("@2\n  (LOOP) D=D+1 // count\n(END)@END\n0;JMP\n");
+        let mut lines = Vec::new();
+        while let Some(Ok(line)) = parser.advance() {
+            lines.push((line, parser.get_line_number(), parser.get_span()));
+        }
+        let lines: Vec<_> = lines
+            .iter()
+            .map(|(line, number, span)| (line.as_str(), *number, span.start, span.end))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                ("@2", 1, 0, 2),
+                ("(LOOP)", 2, 5, 11),
+                ("D=D+1", 2, 12, 17),
+                ("(END)", 3, 27, 32),
+                ("@END", 3, 32, 36),
+                ("0;JMP", 4, 37, 42),
+            ]
+        );
+        assert_eq!(parser.instruction_index(), 4);
+    }
+
+    #[test]
+    fn peek_should_return_only_the_label_of_a_combined_line() {
+        let mut parser = Parser::from_source("(LOOP) D=D+1\n");
+        assert_eq!(parser.peek(), Some("(LOOP)"));
+        assert_eq!(parser.advance().unwrap().unwrap(), "(LOOP)");
+        assert_eq!(parser.peek(), Some("D=D+1"));
+        assert_eq!(parser.advance().unwrap().unwrap(), "D=D+1");
+        assert_eq!(parser.peek(), None);
+
+        parser.reset();
+        assert_eq!(parser.advance().unwrap().unwrap(), "(LOOP)");
+    }
+
+    #[test]
+    fn c_fields_should_return_the_dest_comp_and_jump_as_written() {
+        assert_eq!(
+            Parser::c_fields("MD=D+1;JGT"),
+            Some((Some("MD"), "D+1", Some("JGT")))
+        );
+        assert_eq!(Parser::c_fields("D=A"), Some((Some("D"), "A", None)));
+        assert_eq!(Parser::c_fields("0;JMP"), Some((None, "0", Some("JMP"))));
+        assert_eq!(Parser::c_fields("X=D+1"), Some((Some("X"), "D+1", None)));
+    }
+
+    #[test]
+    fn c_fields_should_return_none_for_other_lines() {
+        assert_eq!(Parser::c_fields("@LOOP"), None);
+        assert_eq!(Parser::c_fields("(LOOP)"), None);
+        assert_eq!(Parser::c_fields("D=;JMP"), None);
+        assert_eq!(Parser::c_fields(""), None);
+    }
 }
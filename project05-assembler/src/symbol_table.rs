@@ -1,8 +1,40 @@
 use std::collections::HashMap;
+use std::io::{self, Write};
 
-#[derive(Debug, PartialEq, Eq)]
+use crate::{error::AssemblerError, json::json_string};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SymbolKind {
+  Predefined,
+  Label,
+  Variable,
+  Constant,
+}
+
+impl SymbolKind {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      SymbolKind::Predefined => "predefined",
+      SymbolKind::Label => "label",
+      SymbolKind::Variable => "variable",
+      SymbolKind::Constant => "constant",
+    }
+  }
+}
+
+/// What the table knows about a symbol: the address it stands for and where
+/// that address came from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SymbolEntry {
+  pub address: usize,
+  pub kind: SymbolKind,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct SymbolTable {
-  entries: HashMap<String, usize>
+  entries: HashMap<String, SymbolEntry>,
+  // what `preload` saw, kept through `clear_user_symbols`
+  preloaded: HashMap<String, SymbolEntry>,
 }
 
 impl SymbolTable {
@@ -31,27 +63,147 @@ impl SymbolTable {
     entries.insert("THAT".to_string(), 4);
     entries.insert("SCREEN".to_string(), 16384);
     entries.insert("KBD".to_string(), 24576);
+    let entries = entries
+      .into_iter()
+      .map(|(symbol, address)| {
+        (
+          symbol,
+          SymbolEntry {
+            address,
+            kind: SymbolKind::Predefined,
+          },
+        )
+      })
+      .collect();
 
-    Self { entries }
+    Self {
+      entries,
+      preloaded: HashMap::new(),
+    }
   }
 
-  pub fn add_entry(&mut self, symbol: String, address: usize) {
-      self.entries.entry(symbol).or_insert(address);
+  /// Adds the symbol unless the table already has it. Like `update_entry`,
+  /// it takes an owned `String` as is, or copies a `&str`; the lookups all
+  /// take `&str`.
+  pub fn add_entry(&mut self, symbol: impl Into<String>, address: usize, kind: SymbolKind) {
+    self
+      .entries
+      .entry(symbol.into())
+      .or_insert(SymbolEntry { address, kind });
   }
 
-  pub fn update_entry(&mut self, symbol: String, address: usize) {
-    self.entries.entry(symbol).and_modify(|v| *v = address).or_insert(address);
-}
+  pub fn update_entry(&mut self, symbol: impl Into<String>, address: usize, kind: SymbolKind) {
+    self
+      .entries
+      .insert(symbol.into(), SymbolEntry { address, kind });
+  }
+
+  /// Marks every symbol in the table as preloaded, such as a memory map to
+  /// link against, so that `clear_user_symbols` keeps it. Fails on a symbol
+  /// whose address no A-instruction can hold.
+  pub(crate) fn preload(&mut self) -> Result<(), AssemblerError> {
+    if let Some((symbol, address, _)) = self
+      .sorted()
+      .into_iter()
+      .find(|(_, address, _)| *address > 0x7FFF)
+    {
+      return Err(AssemblerError::AddressOutOfRange {
+        symbol: symbol.clone(),
+        address,
+      });
+    }
+    self.preloaded = self.entries.clone();
+    Ok(())
+  }
+
+  /// Drops every label, variable and constant, keeping the predefined and
+  /// preloaded symbols for the next program. A predefined symbol that a
+  /// label took over gets its own address back.
+  pub fn clear_user_symbols(&mut self) {
+    let preloaded = std::mem::take(&mut self.preloaded);
+    *self = SymbolTable::new();
+    self.entries.extend(
+      preloaded
+        .iter()
+        .map(|(symbol, entry)| (symbol.clone(), *entry)),
+    );
+    self.preloaded = preloaded;
+  }
 
   pub fn contains(&self, given_symbol: &str) -> bool {
     self.entries.contains_key(given_symbol)
   }
 
   pub fn get_address(&self, given_symbol: &str) -> Option<usize> {
-    if let Some((_, &v)) = self.entries.get_key_value(given_symbol) {
-      return Some(v)
+    self.entries.get(given_symbol).map(|entry| entry.address)
+  }
+
+  /// Whether the symbol is predefined, a label, a variable or a constant.
+  pub fn get_kind(&self, given_symbol: &str) -> Option<SymbolKind> {
+    self.entries.get(given_symbol).map(|entry| entry.kind)
+  }
+
+  /// The address and kind of the symbol.
+  pub fn get_entry(&self, given_symbol: &str) -> Option<SymbolEntry> {
+    self.entries.get(given_symbol).copied()
+  }
+
+  /// The first RAM address no variable holds: 16 when there are none, else
+  /// one past the highest variable. Data must stay below 16384, where the
+  /// screen starts.
+  pub fn next_free_ram(&self) -> usize {
+    self
+      .entries
+      .values()
+      .filter(|entry| entry.kind == SymbolKind::Variable)
+      .map(|entry| entry.address + 1)
+      .max()
+      .unwrap_or(16)
+  }
+
+  /// Writes one `name address kind` line per symbol, sorted by address then
+  /// name, where kind is `predefined`, `label`, `variable` or `constant`.
+  pub fn dump<W: Write>(&self, mut writer: W) -> io::Result<()> {
+    for (symbol, address, kind) in self.sorted() {
+      writeln!(writer, "{} {} {}", symbol, address, kind.as_str())?;
     }
-    None
+    Ok(())
+  }
+
+  /// Renders the table as a JSON object mapping each symbol to its address
+  /// and kind, one symbol per line in the same order as `dump`, e.g.
+  /// `"LOOP": {"address": 4, "kind": "label"}`.
+  pub fn to_json(&self) -> String {
+    let entries: Vec<String> = self
+      .sorted()
+      .into_iter()
+      .map(|(symbol, address, kind)| {
+        format!(
+          "  {}: {{\"address\": {}, \"kind\": \"{}\"}}",
+          json_string(symbol),
+          address,
+          kind.as_str()
+        )
+      })
+      .collect();
+    format!("{{\n{}\n}}\n", entries.join(",\n"))
+  }
+
+  // Every symbol with its address and kind, by address then name
+  fn sorted(&self) -> Vec<(&String, usize, SymbolKind)> {
+    let mut symbols: Vec<(&String, usize, SymbolKind)> = self
+      .entries
+      .iter()
+      .map(|(symbol, entry)| (symbol, entry.address, entry.kind))
+      .collect();
+    symbols.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(b.0)));
+    symbols
+  }
+}
+
+impl Default for SymbolTable {
+  fn default() -> Self {
+    Self::new()
   }
 }
 
@@ -69,9 +221,9 @@ mod tests {
   #[test]
   fn should_return_true_if_the_symboltable_contains_the_given_symbol() {
     let symbol_table = SymbolTable::new();
-    assert_eq!(symbol_table.contains("R0"), true);
-    assert_eq!(symbol_table.contains("LCL"), true);
-    assert_eq!(symbol_table.contains("KBD"), true);
+    assert!(symbol_table.contains("R0"));
+    assert!(symbol_table.contains("LCL"));
+    assert!(symbol_table.contains("KBD"));
   }
 
   #[test] 
@@ -81,4 +233,157 @@ mod tests {
     assert_eq!(symbol_table.get_address("R0"), Some(0));
     assert_eq!(symbol_table.get_address("LCL"), Some(1));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn should_register_every_predefined_symbol_with_its_canonical_address() {
+    let symbol_table = SymbolTable::new();
+    for register in 0..16 {
+      assert_eq!(
+        symbol_table.get_address(&format!("R{}", register)),
+        Some(register)
+      );
+    }
+    assert_eq!(symbol_table.get_address("SP"), Some(0));
+    assert_eq!(symbol_table.get_address("LCL"), Some(1));
+    assert_eq!(symbol_table.get_address("ARG"), Some(2));
+    assert_eq!(symbol_table.get_address("THIS"), Some(3));
+    assert_eq!(symbol_table.get_address("THAT"), Some(4));
+    assert_eq!(symbol_table.get_address("SCREEN"), Some(16384));
+    assert_eq!(symbol_table.get_address("KBD"), Some(24576));
+    assert_eq!(symbol_table.entries.len(), 23);
+  }
+
+  #[test]
+  fn should_keep_the_kind_of_each_symbol_with_its_address() {
+    let mut symbol_table = SymbolTable::new();
+    symbol_table.add_entry("LOOP", 4, SymbolKind::Label);
+    symbol_table.add_entry("i", 16, SymbolKind::Variable);
+    assert_eq!(symbol_table.get_kind("LOOP"), Some(SymbolKind::Label));
+    assert_eq!(symbol_table.get_kind("KBD"), Some(SymbolKind::Predefined));
+    assert_eq!(
+      symbol_table.get_entry("i"),
+      Some(SymbolEntry {
+        address: 16,
+        kind: SymbolKind::Variable
+      })
+    );
+    assert_eq!(symbol_table.get_kind("missing"), None);
+  }
+
+  #[test]
+  fn entries_added_from_a_string_or_a_str_should_be_found_by_str() {
+    let mut symbol_table = SymbolTable::new();
+    let label = String::from("LOOP");
+    symbol_table.add_entry(label, 4, SymbolKind::Label);
+    symbol_table.add_entry("i", 16, SymbolKind::Variable);
+    symbol_table.update_entry("END", 9, SymbolKind::Label);
+    assert!(symbol_table.contains("LOOP"));
+    assert_eq!(symbol_table.get_address("LOOP"), Some(4));
+    assert_eq!(symbol_table.get_kind("i"), Some(SymbolKind::Variable));
+    assert_eq!(
+      symbol_table.get_entry("END"),
+      Some(SymbolEntry {
+        address: 9,
+        kind: SymbolKind::Label,
+      })
+    );
+  }
+
+  #[test]
+  fn clear_user_symbols_should_keep_only_the_predefined_symbols() {
+    let mut symbol_table = SymbolTable::new();
+    symbol_table.add_entry("LOOP", 4, SymbolKind::Label);
+    symbol_table.add_entry("i", 16, SymbolKind::Variable);
+    symbol_table.update_entry("KBD", 9, SymbolKind::Label);
+    symbol_table.clear_user_symbols();
+    assert_eq!(symbol_table, SymbolTable::new());
+  }
+
+  #[test]
+  fn clear_user_symbols_should_keep_preloaded_symbols() {
+    let mut symbol_table = SymbolTable::new();
+    symbol_table.add_entry("VIDEO", 20000, SymbolKind::Predefined);
+    symbol_table.add_entry("buffer", 16, SymbolKind::Variable);
+    symbol_table.preload().unwrap();
+    symbol_table.update_entry("SCREEN", 0, SymbolKind::Label);
+    symbol_table.add_entry("i", 17, SymbolKind::Variable);
+    symbol_table.clear_user_symbols();
+    assert_eq!(
+      symbol_table.get_entry("SCREEN"),
+      Some(SymbolEntry {
+        address: 16384,
+        kind: SymbolKind::Predefined
+      })
+    );
+    assert_eq!(symbol_table.get_address("VIDEO"), Some(20000));
+    assert_eq!(symbol_table.get_kind("buffer"), Some(SymbolKind::Variable));
+    assert!(!symbol_table.contains("i"));
+  }
+
+  #[test]
+  fn next_free_ram_should_follow_the_last_variable() {
+    let mut symbol_table = SymbolTable::new();
+    assert_eq!(symbol_table.next_free_ram(), 16);
+    symbol_table.add_entry("LOOP", 40, SymbolKind::Label);
+    for (address, variable) in ["i", "j", "k"].iter().enumerate() {
+      symbol_table.add_entry(variable.to_string(), address + 16, SymbolKind::Variable);
+    }
+    assert_eq!(symbol_table.next_free_ram(), 19);
+  }
+
+  #[test]
+  fn next_free_ram_should_follow_a_variable_past_16_bits() {
+    let mut symbol_table = SymbolTable::new();
+    symbol_table.add_entry("top", 65535, SymbolKind::Variable);
+    assert_eq!(symbol_table.next_free_ram(), 65536);
+    symbol_table.add_entry("far", 70000, SymbolKind::Variable);
+    assert_eq!(symbol_table.next_free_ram(), 70001);
+  }
+
+  #[test]
+  fn dump_should_sort_symbols_by_address_then_name() {
+    let mut symbol_table = SymbolTable::new();
+    symbol_table.add_entry("LOOP", 4, SymbolKind::Label);
+    symbol_table.add_entry("i", 16, SymbolKind::Variable);
+    let mut dump: Vec<u8> = Vec::new();
+    symbol_table.dump(&mut dump).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(lines.len(), 25);
+    assert_eq!(
+      &lines[..3],
+      ["R0 0 predefined", "SP 0 predefined", "LCL 1 predefined"]
+    );
+    assert!(lines.contains(&"LOOP 4 label"));
+    assert_eq!(
+      &lines[22..],
+      [
+        "i 16 variable",
+        "SCREEN 16384 predefined",
+        "KBD 24576 predefined"
+      ]
+    );
+  }
+
+  #[test]
+  fn to_json_should_write_one_symbol_per_line_in_dump_order() {
+    let mut symbol_table = SymbolTable::new();
+    symbol_table.add_entry("LOOP", 4, SymbolKind::Label);
+    symbol_table.add_entry("i", 16, SymbolKind::Variable);
+    let json = symbol_table.to_json();
+    let lines: Vec<&str> = json.lines().collect();
+    assert_eq!(lines.len(), 25 + 2);
+    assert_eq!(lines[0], "{");
+    assert_eq!(
+      lines[1],
+      "  \"R0\": {\"address\": 0, \"kind\": \"predefined\"},"
+    );
+    assert!(lines.contains(&"  \"LOOP\": {\"address\": 4, \"kind\": \"label\"},"));
+    assert!(lines.contains(&"  \"i\": {\"address\": 16, \"kind\": \"variable\"},"));
+    assert_eq!(
+      lines[lines.len() - 2],
+      "  \"KBD\": {\"address\": 24576, \"kind\": \"predefined\"}"
+    );
+    assert_eq!(lines[lines.len() - 1], "}");
+  }
+}
@@ -0,0 +1,39 @@
+use crate::hack_assembler::HackAssembler;
+
+/// Assembles Hack source into the `.hack` text format without touching the
+/// filesystem, for in-browser builds. Errors come back as their message,
+/// e.g. `line 2: unknown comp 'X'`.
+pub fn assemble_wasm(source: &str) -> Result<String, String> {
+    let words = HackAssembler::assemble_str(source).map_err(|err| err.to_string())?;
+    Ok(words
+        .iter()
+        .map(|word| format!("{:016b}\n", word))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn assemble_wasm_should_match_the_core_api() {
+        let source = fs::read_to_string("asm-files/Max.asm").unwrap();
+        let mut expected: Vec<u8> = Vec::new();
+        HackAssembler::from_source(&source)
+            .assemble_to_writer(&mut expected)
+            .unwrap();
+        assert_eq!(
+            assemble_wasm(&source).unwrap(),
+            String::from_utf8(expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn assemble_wasm_should_return_the_error_message() {
+        assert_eq!(
+            assemble_wasm("@1\nD=X\n").unwrap_err(),
+            "line 2: unknown comp 'X'"
+        );
+    }
+}
@@ -0,0 +1,13 @@
+use project05_assembler::hack_assembler::HackAssembler;
+
+#[path = "../benches/program.rs"]
+mod program;
+
+#[test]
+fn the_benchmark_program_should_assemble() {
+    let source = program::generate_program(4000);
+    let words = HackAssembler::assemble_str(&source).unwrap();
+    assert_eq!(words.len(), 28000);
+    // the first loop jumps back to its own label at address 0
+    assert_eq!(words[3], 0);
+}
@@ -0,0 +1,739 @@
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+const ADD_HACK: &str = "0000000000000010
+1110110000010000
+0000000000000011
+1110000010010000
+0000000000000000
+1110001100001000
+";
+
+fn assembler() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_project05-assembler"))
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("hack-assembler-cli-{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn should_assemble_add_asm_into_the_given_output_file() {
+    let dir = temp_dir("output");
+    let output = dir.join("Out.hack");
+
+    let status = assembler()
+        .arg("asm-files/Add.asm")
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+    assert_eq!(fs::read_to_string(output).unwrap(), ADD_HACK);
+}
+
+#[test]
+fn should_write_the_output_next_to_the_input_by_default() {
+    let dir = temp_dir("default");
+    let input = dir.join("Add.asm");
+    fs::copy("asm-files/Add.asm", &input).unwrap();
+
+    let status = assembler().arg(&input).status().unwrap();
+
+    assert!(status.success());
+    assert_eq!(fs::read_to_string(dir.join("Add.hack")).unwrap(), ADD_HACK);
+}
+
+#[test]
+fn should_fail_with_a_message_when_the_input_is_missing() {
+    let output = assembler().arg("asm-files/Missing.asm").output().unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("asm-files/Missing.asm"));
+}
+
+#[test]
+fn should_fail_with_the_usage_when_no_input_is_given() {
+    let output = assembler().output().unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Usage"));
+}
+
+#[test]
+fn should_read_stdin_and_write_stdout_when_paths_are_dashes() {
+    let mut child = assembler()
+        .args(["-", "-o", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let source = fs::read_to_string("asm-files/Add.asm").unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), ADD_HACK);
+}
+
+#[test]
+fn should_write_stdout_by_default_when_reading_stdin() {
+    let mut child = assembler()
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"@2\nD=A\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0000000000000010\n1110110000010000\n"
+    );
+}
+
+#[test]
+fn should_write_a_listing_when_asked_to() {
+    let dir = temp_dir("listing");
+    let listing = dir.join("Add.lst");
+
+    let status = assembler()
+        .arg("asm-files/Add.asm")
+        .arg("-o")
+        .arg(dir.join("Add.hack"))
+        .arg("--listing")
+        .arg(&listing)
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+    let listing = fs::read_to_string(listing).unwrap();
+    assert_eq!(listing.lines().count(), 6);
+    assert_eq!(
+        listing.lines().next(),
+        Some("0000  @2  // 0000000000000010")
+    );
+}
+
+#[test]
+fn should_dump_the_symbol_table_when_asked_to() {
+    let dir = temp_dir("dump-symbols");
+    let symbols = dir.join("Sum1ToN.sym");
+
+    let status = assembler()
+        .arg("asm-files/Sum1ToN.asm")
+        .arg("-o")
+        .arg(dir.join("Sum1ToN.hack"))
+        .arg("--dump-symbols")
+        .arg(&symbols)
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+    let symbols = fs::read_to_string(symbols).unwrap();
+    assert!(symbols.lines().any(|line| line == "i 16 variable"));
+    assert!(symbols.lines().any(|line| line == "LOOP 4 label"));
+}
+
+#[test]
+fn should_write_big_endian_bytes_with_binary() {
+    let dir = temp_dir("binary");
+    let output = dir.join("Add.bin");
+
+    let status = assembler()
+        .arg("asm-files/Add.asm")
+        .arg("--binary")
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+    let bytes = fs::read(output).unwrap();
+    assert_eq!(bytes.len(), 12);
+    assert_eq!(&bytes[..4], [0x00, 0x02, 0xec, 0x10]);
+}
+
+#[test]
+fn should_write_hex_words_with_hex() {
+    let mut child = assembler()
+        .args(["-", "--hex"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"@255\nD=A\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "00FF\nEC10\n");
+}
+
+#[test]
+fn should_assemble_every_input_given_to_it() {
+    let dir = temp_dir("multiple");
+    let add = dir.join("Add.asm");
+    let sum = dir.join("Sum1ToN.asm");
+    fs::copy("asm-files/Add.asm", &add).unwrap();
+    fs::copy("asm-files/Sum1ToN.asm", &sum).unwrap();
+
+    let output = assembler().arg(&add).arg(&sum).output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(dir.join("Add.hack")).unwrap(), ADD_HACK);
+    let sum_hack = fs::read_to_string(dir.join("Sum1ToN.hack")).unwrap();
+    assert_eq!(sum_hack.lines().count(), 20);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Add.asm: assembled into"));
+    assert!(stderr.contains("Sum1ToN.asm: assembled into"));
+}
+
+#[test]
+fn should_keep_going_past_a_failed_input() {
+    let dir = temp_dir("multiple-failure");
+    let add = dir.join("Add.asm");
+    fs::copy("asm-files/Add.asm", &add).unwrap();
+
+    let output = assembler()
+        .arg(dir.join("Missing.asm"))
+        .arg(&add)
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(fs::read_to_string(dir.join("Add.hack")).unwrap(), ADD_HACK);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Missing.asm"));
+}
+
+#[test]
+fn should_assemble_every_asm_file_in_a_directory() {
+    let dir = temp_dir("dir");
+    fs::copy("asm-files/Add.asm", dir.join("Add.asm")).unwrap();
+    fs::copy("asm-files/Max.asm", dir.join("Max.asm")).unwrap();
+    fs::write(dir.join("notes.txt"), "not an assembly file").unwrap();
+    fs::create_dir(dir.join("nested")).unwrap();
+    fs::copy("asm-files/Add.asm", dir.join("nested").join("Nested.asm")).unwrap();
+
+    let output = assembler().arg("--dir").arg(&dir).output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(dir.join("Add.hack")).unwrap(), ADD_HACK);
+    assert!(dir.join("Max.hack").exists());
+    assert!(!dir.join("notes.hack").exists());
+    assert!(!dir.join("nested").join("Nested.hack").exists());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("assembled 2 of 2 files"));
+}
+
+#[test]
+fn should_descend_into_subdirectories_with_recursive() {
+    let dir = temp_dir("dir-recursive");
+    fs::copy("asm-files/Add.asm", dir.join("Add.asm")).unwrap();
+    fs::create_dir(dir.join("nested")).unwrap();
+    fs::copy("asm-files/Add.asm", dir.join("nested").join("Nested.asm")).unwrap();
+
+    let output = assembler()
+        .arg("--dir")
+        .arg(&dir)
+        .arg("--recursive")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(dir.join("nested").join("Nested.hack")).unwrap(),
+        ADD_HACK
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("assembled 2 of 2 files"));
+}
+
+#[test]
+fn should_assemble_many_files_concurrently_like_one_at_a_time() {
+    let dir = temp_dir("concurrent");
+    let fixtures = [
+        "Add",
+        "Max",
+        "Sum1ToN",
+        "Labels",
+        "Devices",
+        "ForwardVariable",
+    ];
+    let mut inputs = Vec::new();
+    for round in 0..3 {
+        for fixture in fixtures {
+            let input = dir.join(format!("{}{}.asm", fixture, round));
+            fs::copy(format!("asm-files/{}.asm", fixture), &input).unwrap();
+            inputs.push((fixture, input));
+        }
+    }
+
+    let output = assembler()
+        .args(inputs.iter().map(|(_, input)| input))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    for (fixture, input) in &inputs {
+        let single = assembler()
+            .arg(format!("asm-files/{}.asm", fixture))
+            .args(["-o", "-"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            fs::read(input.with_extension("hack")).unwrap(),
+            single.stdout
+        );
+    }
+    // reports come back in the order the inputs were given
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reported: Vec<&str> = stderr
+        .lines()
+        .filter_map(|line| line.strip_suffix(".hack"))
+        .filter_map(|line| line.split(": assembled into").next())
+        .collect();
+    let given: Vec<String> = inputs
+        .iter()
+        .map(|(_, input)| input.to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(reported, given);
+}
+
+#[test]
+fn should_print_warnings_without_failing() {
+    let mut child = assembler()
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"(NEVER)\n@0\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("-: warning: line 1: label 'NEVER' is never used"));
+}
+
+#[test]
+fn should_accept_shifts_only_with_extended_alu() {
+    let run = |extra: &[&str]| {
+        let mut child = assembler()
+            .arg("-")
+            .args(extra)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"D=D<<\n").unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    assert!(!run(&[]).status.success());
+    let output = run(&["--extended-alu"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1010110000010000\n"
+    );
+}
+
+#[test]
+fn should_check_inputs_without_writing_anything() {
+    let dir = temp_dir("check");
+    let input = dir.join("Add.asm");
+    fs::copy("asm-files/Add.asm", &input).unwrap();
+
+    let output = assembler().arg(&input).arg("--check").output().unwrap();
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(!dir.join("Add.hack").exists());
+
+    let invalid = dir.join("Invalid.asm");
+    fs::write(&invalid, "@40000\nD=X\n").unwrap();
+    let output = assembler().arg(&invalid).arg("--check").output().unwrap();
+
+    assert!(!output.status.success());
+    assert!(!dir.join("Invalid.hack").exists());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("line 1: constant 40000 is out of range"));
+    assert!(stderr.contains("line 2: unknown comp 'X'"));
+    // stderr is no terminal here, so the snippet comes without colors
+    assert!(stderr.contains("2 | D=X\n  |   ^\n"));
+}
+
+#[test]
+fn should_print_statistics_with_stats() {
+    let dir = temp_dir("stats");
+
+    let output = assembler()
+        .arg("asm-files/Sum1ToN.asm")
+        .arg("-o")
+        .arg(dir.join("Sum1ToN.hack"))
+        .arg("--stats")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "asm-files/Sum1ToN.asm: 10 A-instructions, 10 C-instructions, 2 labels, 2 variables, 20 ROM words"
+    ));
+}
+
+#[test]
+fn should_write_a_sourcemap_when_asked_to() {
+    let dir = temp_dir("sourcemap");
+    let sourcemap = dir.join("Add.map.json");
+
+    let status = assembler()
+        .arg("asm-files/Add.asm")
+        .arg("-o")
+        .arg(dir.join("Add.hack"))
+        .arg("--sourcemap")
+        .arg(&sourcemap)
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+    let sourcemap = fs::read_to_string(sourcemap).unwrap();
+    assert_eq!(
+        sourcemap.lines().nth(1),
+        Some("  {\"address\": 0, \"line\": 7},")
+    );
+}
+
+#[test]
+fn should_print_only_errors_with_quiet() {
+    let dir = temp_dir("quiet");
+    let unused = dir.join("Unused.asm");
+    fs::write(&unused, "(NEVER)\n@0\n").unwrap();
+    fs::copy("asm-files/Add.asm", dir.join("Add.asm")).unwrap();
+
+    let output = assembler()
+        .arg(&unused)
+        .arg(dir.join("Add.asm"))
+        .args(["--stats", "--quiet"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    let output = assembler()
+        .arg(dir.join("Missing.asm"))
+        .arg("--quiet")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Missing.asm"));
+}
+
+#[test]
+fn should_report_a_single_input_with_verbose() {
+    let dir = temp_dir("verbose");
+    let input = dir.join("Add.asm");
+    fs::copy("asm-files/Add.asm", &input).unwrap();
+
+    let quiet_by_default = assembler().arg(&input).output().unwrap();
+    let verbose = assembler().arg(&input).arg("--verbose").output().unwrap();
+
+    assert!(quiet_by_default.stderr.is_empty());
+    let stderr = String::from_utf8_lossy(&verbose.stderr);
+    assert!(stderr.contains("Add.asm: assembled into"));
+    assert!(stderr.contains("assembled 1 of 1 files"));
+}
+
+#[test]
+fn should_report_each_mismatch_with_a_reference_when_diffing() {
+    let dir = temp_dir("diff");
+    let input = dir.join("Add.asm");
+    fs::copy("asm-files/Add.asm", &input).unwrap();
+    let reference = dir.join("Reference.hack");
+    fs::write(&reference, ADD_HACK).unwrap();
+
+    let output = assembler()
+        .arg(&input)
+        .arg("--diff")
+        .arg(&reference)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(!dir.join("Add.hack").exists());
+
+    fs::write(
+        &reference,
+        ADD_HACK.replacen("0000000000000011", "0000000000000100", 1),
+    )
+    .unwrap();
+    let output = assembler()
+        .arg(&input)
+        .arg("--diff")
+        .arg(&reference)
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("address 2: 0000000000000011, expected 0000000000000100"));
+    assert_eq!(stderr.matches("address").count(), 1);
+}
+
+#[test]
+fn should_leave_an_existing_output_untouched_when_assembly_fails() {
+    let dir = temp_dir("atomic");
+    let output = dir.join("Out.hack");
+    fs::write(&output, ADD_HACK).unwrap();
+
+    let status = assembler()
+        .arg("asm-files/BadComp.asm")
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+
+    assert!(!status.success());
+    assert_eq!(fs::read_to_string(&output).unwrap(), ADD_HACK);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+}
+
+#[test]
+fn should_verify_each_input_against_the_reference_of_the_same_name() {
+    let dir = temp_dir("verify-dir");
+    let inputs = dir.join("inputs");
+    let references = dir.join("references");
+    fs::create_dir_all(&inputs).unwrap();
+    fs::create_dir_all(&references).unwrap();
+    fs::copy("asm-files/Add.asm", inputs.join("Add.asm")).unwrap();
+    fs::write(references.join("Add.hack"), ADD_HACK).unwrap();
+    fs::copy("asm-files/Max.asm", inputs.join("Max.asm")).unwrap();
+    fs::write(references.join("Max.hack"), ADD_HACK).unwrap();
+
+    let output = assembler()
+        .arg("--dir")
+        .arg(&inputs)
+        .arg("--verify-dir")
+        .arg(&references)
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(!inputs.join("Add.hack").exists());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Add.asm: pass"));
+    assert!(stderr.contains("Max.asm: fail: differs from"));
+    assert!(stderr.contains("at line 1"));
+    assert!(stderr.contains("passed 1 of 2 files"));
+}
+
+#[test]
+fn should_write_an_empty_output_for_an_empty_input() {
+    let dir = temp_dir("empty");
+    let input = dir.join("Empty.asm");
+    fs::write(&input, "").unwrap();
+
+    let status = assembler().arg(&input).status().unwrap();
+
+    assert!(status.success());
+    assert_eq!(fs::read_to_string(dir.join("Empty.hack")).unwrap(), "");
+}
+
+#[test]
+fn should_fail_when_variables_run_past_the_max_ram() {
+    let output = assembler()
+        .arg("asm-files/Sum1ToN.asm")
+        .arg("--check")
+        .args(["--max-ram", "17"])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no RAM left for variable 'sum'"));
+
+    let output = assembler()
+        .arg("asm-files/Sum1ToN.asm")
+        .args(["--max-ram", "lots"])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid number 'lots'"));
+
+    let output = assembler()
+        .arg("asm-files/Sum1ToN.asm")
+        .args(["--max-ram", "99999"])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("max RAM 99999 is above 32768"));
+}
+
+#[test]
+fn should_print_statistics_as_json_with_json() {
+    let output = assembler()
+        .arg("asm-files/Sum1ToN.asm")
+        .arg("--check")
+        .arg("--stats")
+        .arg("--json")
+        .arg("--quiet")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "{\"input\": \"asm-files/Sum1ToN.asm\", \"stats\": {\"a_instructions\": 10, \"c_instructions\": 10, \"labels\": 2, \"variables\": 2, \"rom_words\": 20}}\n"
+    );
+    assert!(output.stderr.is_empty());
+
+    let output = assembler()
+        .args(["asm-files/Sum1ToN.asm", "-o", "-", "--stats", "--json"])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn should_annotate_each_line_with_annotate() {
+    let output = assembler()
+        .arg("asm-files/Add.asm")
+        .args(["-o", "-", "--annotate"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[0], "0000000000000010 // @2");
+    assert_eq!(lines[3], "1110000010010000 // D=D+A");
+}
+
+#[test]
+fn should_pad_the_output_to_the_given_size() {
+    let dir = temp_dir("pad");
+    let output = dir.join("Add.hack");
+
+    let status = assembler()
+        .arg("asm-files/Add.asm")
+        .arg("-o")
+        .arg(&output)
+        .args(["--pad", "8"])
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+    let padded = fs::read_to_string(&output).unwrap();
+    assert_eq!(
+        padded,
+        format!("{}{}", ADD_HACK, "0000000000000000\n".repeat(2))
+    );
+
+    let result = assembler()
+        .arg("asm-files/Add.asm")
+        .arg("-o")
+        .arg(&output)
+        .args(["--pad", "4"])
+        .output()
+        .unwrap();
+
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("program has 6 instructions"));
+    assert_eq!(fs::read_to_string(&output).unwrap(), padded);
+}
+
+#[test]
+fn should_splice_in_the_files_an_input_includes() {
+    let dir = temp_dir("include");
+    fs::write(dir.join("Main.asm"), "// include \"lib/Init.asm\"\n@1\n").unwrap();
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("lib/Init.asm"), "@2\n").unwrap();
+
+    let output = assembler()
+        .arg(dir.join("Main.asm"))
+        .args(["-o", "-"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "0000000000000010\n0000000000000001\n"
+    );
+}
+
+#[test]
+fn should_fail_on_an_input_that_includes_itself() {
+    let output = assembler()
+        .args(["asm-files/include/SelfInclude.asm", "-o", "-"])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("includes itself"));
+}
+
+#[test]
+fn should_create_the_directories_of_the_output() {
+    let dir = temp_dir("output-dirs");
+    let output = dir.join("missing/dir/Add.hack");
+
+    let status = assembler()
+        .arg("asm-files/Add.asm")
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+    assert_eq!(fs::read_to_string(output).unwrap(), ADD_HACK);
+}
+
+#[test]
+fn should_name_the_output_when_it_cannot_be_written() {
+    let dir = temp_dir("unwritable-output");
+    fs::write(dir.join("file"), "").unwrap();
+    let output = dir.join("file/Add.hack");
+
+    let result = assembler()
+        .arg("asm-files/Add.asm")
+        .arg("-o")
+        .arg(&output)
+        .output()
+        .unwrap();
+
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains(&output.display().to_string()), "{}", stderr);
+}